@@ -0,0 +1,182 @@
+use std::{
+    fmt::{self, Display, Formatter, Write},
+    fs, io, mem,
+    ops::Range,
+    str::FromStr,
+};
+
+/// How benchmark threads are placed on CPUs. Pinning is Linux only, through `sched_setaffinity`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Placement {
+    /// Left to the OS scheduler, which may migrate threads at will.
+    Os,
+    /// Consecutive threads go to different NUMA nodes in turn, spreading them over as many nodes as possible.
+    RoundRobin,
+    /// Threads fill up one NUMA node's CPUs before moving on to the next.
+    Compact,
+    /// Thread `i` goes to the `i`-th CPU of the list, wrapping around.
+    List(Vec<usize>),
+}
+
+impl FromStr for Placement {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "os" => Ok(Self::Os),
+            "round-robin" => Ok(Self::RoundRobin),
+            "compact" => Ok(Self::Compact),
+            s => match parse_cpu_list(s) {
+                Some(cpus) if !cpus.is_empty() => Ok(Self::List(cpus)),
+                _ => Err(format!(
+                    "Unexpected placement: '{s}'. Expected os, round-robin, compact, or a CPU list like 0,2,4-7."
+                )),
+            },
+        }
+    }
+}
+
+impl Display for Placement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Os => write!(f, "os"),
+            Self::RoundRobin => write!(f, "round-robin"),
+            Self::Compact => write!(f, "compact"),
+            Self::List(cpus) => write!(f, "{}", cpus.iter().map(usize::to_string).collect::<Vec<_>>().join(",")),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Cpu {
+    pub id: usize,
+    pub node: usize,
+}
+
+/// A placement resolved against the CPUs that this process is allowed to run on.
+#[derive(Clone, Debug)]
+pub struct Plan {
+    placement: Placement,
+    /// The CPU of thread `i` is `cpus[i % cpus.len()]`. Empty when the OS places threads.
+    cpus: Vec<Cpu>,
+}
+
+impl Placement {
+    pub fn plan(&self) -> io::Result<Plan> {
+        let allowed = if *self == Self::Os { Vec::new() } else { allowed_cpus()? };
+        let cpus = match self {
+            Self::Os => Vec::new(),
+            Self::Compact => {
+                let mut cpus = allowed;
+                cpus.sort_by_key(|cpu| (cpu.node, cpu.id));
+                cpus
+            }
+            Self::RoundRobin => {
+                let mut nodes = allowed.iter().map(|cpu| cpu.node).collect::<Vec<_>>();
+                nodes.sort();
+                nodes.dedup();
+                let per_node = nodes
+                    .iter()
+                    .map(|&node| allowed.iter().copied().filter(|cpu| cpu.node == node).collect::<Vec<_>>())
+                    .collect::<Vec<_>>();
+                let rounds = per_node.iter().map(Vec::len).max().unwrap_or(0);
+                (0..rounds).flat_map(|round| per_node.iter().filter_map(move |cpus| cpus.get(round).copied())).collect()
+            }
+            Self::List(ids) => ids
+                .iter()
+                .map(|&id| {
+                    allowed.iter().copied().find(|cpu| cpu.id == id).ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("CPU {id} is not available to this process"),
+                        )
+                    })
+                })
+                .collect::<io::Result<_>>()?,
+        };
+        Ok(Plan { placement: self.clone(), cpus })
+    }
+}
+
+impl Plan {
+    /// The CPU that thread `thread_index` runs on, if it is pinned.
+    pub fn cpu(&self, thread_index: usize) -> Option<Cpu> {
+        (!self.cpus.is_empty()).then(|| self.cpus[thread_index % self.cpus.len()])
+    }
+
+    /// Pins the calling thread to the CPU planned for `thread_index`. Does nothing if the OS places threads.
+    pub fn pin(&self, thread_index: usize) {
+        if let Some(cpu) = self.cpu(thread_index) {
+            pin_current_thread(cpu.id).unwrap_or_else(|err| panic!("could not pin thread to CPU {}: {err}", cpu.id));
+        }
+    }
+
+    /// One line per group of threads, e.g. `writers: 0 -> cpu0 (node0), 1 -> cpu1 (node0)`.
+    pub fn describe(&self, groups: &[(&str, Range<usize>)]) -> String {
+        let mut description = format!("Thread placement: {}", self.placement);
+        for (name, threads) in groups {
+            let threads = threads.clone().map(|thread| match self.cpu(thread) {
+                Some(Cpu { id, node }) => format!("{thread} -> cpu{id} (node{node})"),
+                None => format!("{thread} -> any"),
+            });
+            write!(description, "\n  {name}: {}", threads.collect::<Vec<_>>().join(", ")).unwrap();
+        }
+        description
+    }
+}
+
+/// Pins the calling thread to one CPU.
+pub fn pin_current_thread(cpu: usize) -> io::Result<()> {
+    // SAFETY: the set is a plain bitmask, initialised before use, and only ever read by the kernel
+    unsafe {
+        let mut set = mem::zeroed::<libc::cpu_set_t>();
+        libc::CPU_ZERO(&mut set);
+        libc::CPU_SET(cpu, &mut set);
+        match libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+}
+
+/// The CPUs this process may run on, with the NUMA node of each. Systems without NUMA information are one node.
+pub fn allowed_cpus() -> io::Result<Vec<Cpu>> {
+    // SAFETY: the set is a plain bitmask that the kernel fills in
+    let set = unsafe {
+        let mut set = mem::zeroed::<libc::cpu_set_t>();
+        if libc::sched_getaffinity(0, mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        set
+    };
+    let nodes = numa_nodes();
+    Ok((0..libc::CPU_SETSIZE as usize)
+        // SAFETY: `cpu` is within the set
+        .filter(|&cpu| unsafe { libc::CPU_ISSET(cpu, &set) })
+        .map(|id| Cpu { id, node: nodes.iter().find(|(_, cpus)| cpus.contains(&id)).map_or(0, |&(node, _)| node) })
+        .collect())
+}
+
+/// The CPUs of every NUMA node, from sysfs.
+fn numa_nodes() -> Vec<(usize, Vec<usize>)> {
+    let Ok(entries) = fs::read_dir("/sys/devices/system/node") else { return Vec::new() };
+    entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let node = entry.file_name().to_str()?.strip_prefix("node")?.parse().ok()?;
+            let cpus = parse_cpu_list(fs::read_to_string(entry.path().join("cpulist")).ok()?.trim())?;
+            Some((node, cpus))
+        })
+        .collect()
+}
+
+/// Parses the kernel's CPU list format, e.g. `0,2,4-7`.
+fn parse_cpu_list(s: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for range in s.split(',').filter(|range| !range.is_empty()) {
+        match range.split_once('-') {
+            Some((first, last)) => cpus.extend(first.parse::<usize>().ok()?..=last.parse().ok()?),
+            None => cpus.push(range.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
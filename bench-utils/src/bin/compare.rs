@@ -0,0 +1,114 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use bench_utils::{
+    memory,
+    record::{self, Record, RecordKey},
+    stats::{Summary, WelchTest},
+    units::Bytes,
+};
+use clap::{arg, command, value_parser};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Verdict {
+    Unchanged,
+    Improved,
+    Regressed,
+}
+
+fn main() -> ExitCode {
+    let args = command!()
+        .about("Compare benchmark results against a baseline, failing if any case regressed")
+        .arg(arg!(<BASELINE> "results file to compare against").value_parser(value_parser!(PathBuf)))
+        .arg(arg!(<CURRENT> "results file of the run under test").value_parser(value_parser!(PathBuf)))
+        .arg(
+            arg!(-t --threshold <PERCENT> "smallest change of the mean that counts, even if significant")
+                .value_parser(value_parser!(f64))
+                .default_value("5"),
+        )
+        .get_matches();
+
+    let baseline = latest_by_key(load(args.get_one::<PathBuf>("BASELINE").unwrap()));
+    let current = load(args.get_one::<PathBuf>("CURRENT").unwrap());
+    let threshold = args.get_one::<f64>("threshold").copied().unwrap() / 100.0;
+
+    let mut regressions = 0;
+    let mut current = latest_by_key(current).into_values().collect::<Vec<_>>();
+    current.sort_by_key(Record::key);
+    for record in current {
+        let name = describe(&record);
+        let Some(baseline) = baseline.get(&record.key()) else {
+            println!("{name}: no baseline");
+            continue;
+        };
+        let (before, after) = (Summary::new(baseline.samples.clone()), Summary::new(record.samples.clone()));
+        let change = (after.mean - before.mean) / before.mean;
+        let test = WelchTest::new(&before, &after);
+        // without enough samples to test significance, the threshold alone decides
+        let significant = test.is_none_or(|test| test.significant);
+        let better = if record.higher_is_better { change > 0.0 } else { change < 0.0 };
+        let verdict = match significant && change.abs() >= threshold {
+            false => Verdict::Unchanged,
+            true if better => Verdict::Improved,
+            true => Verdict::Regressed,
+        };
+        regressions += (verdict == Verdict::Regressed) as usize;
+
+        let test = match test {
+            Some(WelchTest { t, degrees_of_freedom, .. }) => format!("t = {t:.2}, df = {degrees_of_freedom:.1}"),
+            None => "too few trials to test".to_owned(),
+        };
+        println!(
+            "{name}: {:.2} -> {:.2} {} ({:+.2}%, {test}): {verdict:?}",
+            before.mean,
+            after.mean,
+            record.unit,
+            change * 100.0
+        );
+        if let Some(memory) = compare_peak_memory(baseline, &record) {
+            println!("  peak memory: {memory}");
+        }
+    }
+
+    if regressions > 0 {
+        println!("{regressions} regressions");
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn load(path: &Path) -> Vec<Record> {
+    record::load(path).unwrap_or_else(|err| panic!("could not read results from {}: {err}", path.display()))
+}
+
+/// Results files are appended to, so a later record of the same case supersedes the earlier ones.
+fn latest_by_key(records: Vec<Record>) -> HashMap<RecordKey, Record> {
+    records.into_iter().map(|record| (record.key(), record)).collect()
+}
+
+/// How the peak of every memory gauge tracked in both runs changed, the highest peak over all trials of each.
+fn compare_peak_memory(baseline: &Record, current: &Record) -> Option<String> {
+    let (before, after) = (memory::peaks(&baseline.memory), memory::peaks(&current.memory));
+    let changes = after
+        .iter()
+        .filter_map(|(gauge, &after)| {
+            let before = before.get(gauge).copied().filter(|&before| before > 0)?;
+            let change = (after as f64 - before as f64) / before as f64 * 100.0;
+            Some(format!("{gauge} {} -> {} ({change:+.2}%)", Bytes(before), Bytes(after)))
+        })
+        .collect::<Vec<_>>();
+    (!changes.is_empty()).then(|| changes.join(", "))
+}
+
+fn describe(record: &Record) -> String {
+    let params = record.params.iter().map(|(key, value)| format!("{key}={value}")).collect::<Vec<_>>();
+    if params.is_empty() {
+        format!("{} / {}", record.benchmark, record.case)
+    } else {
+        format!("{} / {} [{}]", record.benchmark, record.case, params.join(", "))
+    }
+}
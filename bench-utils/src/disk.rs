@@ -0,0 +1,79 @@
+use std::{
+    fmt::{Display, Formatter},
+    fs, io,
+    iter::Sum,
+    ops::Add,
+    path::Path,
+};
+
+use crate::units::Bytes;
+
+/// The size of a store's files on disk, by kind.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DiskUsage {
+    pub sst: u64,
+    /// Write-ahead logs.
+    pub wal: u64,
+    /// Manifests, options, info logs, and anything else.
+    pub other: u64,
+}
+
+impl DiskUsage {
+    /// Adds up the files under a database directory, classifying them by RocksDB's file naming: `NNNNNN.sst` for
+    /// tables and `NNNNNN.log` for write-ahead logs.
+    pub fn of(dir: &Path) -> io::Result<Self> {
+        let mut usage = Self::default();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                usage = usage + Self::of(&entry.path())?;
+                continue;
+            }
+            let path = entry.path();
+            let numbered =
+                path.file_stem().and_then(|stem| stem.to_str()).is_some_and(|stem| stem.parse::<u64>().is_ok());
+            match path.extension().and_then(|extension| extension.to_str()) {
+                Some("sst") if numbered => usage.sst += metadata.len(),
+                Some("log") if numbered => usage.wal += metadata.len(),
+                _ => usage.other += metadata.len(),
+            }
+        }
+        Ok(usage)
+    }
+
+    pub fn total(&self) -> u64 {
+        self.sst + self.wal + self.other
+    }
+}
+
+impl Add for DiskUsage {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self { sst: self.sst + rhs.sst, wal: self.wal + rhs.wal, other: self.other + rhs.other }
+    }
+}
+
+impl Sum for DiskUsage {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), Add::add)
+    }
+}
+
+impl Display for DiskUsage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (SST {}, WAL {}, other {})",
+            Bytes(self.total()),
+            Bytes(self.sst),
+            Bytes(self.wal),
+            Bytes(self.other)
+        )
+    }
+}
+
+/// How many bytes on disk every logical byte takes, or `None` for an empty store.
+pub fn space_amplification(on_disk: u64, logical: u64) -> Option<f64> {
+    (logical > 0).then(|| on_disk as f64 / logical as f64)
+}
@@ -0,0 +1,218 @@
+use std::{
+    any::Any,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    panic,
+    str::FromStr,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use rand::{thread_rng, Rng};
+
+/// The kinds of storage call that faults can be injected into.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Point {
+    Write,
+    Read,
+    /// Syncing the write-ahead log of a durable write.
+    Sync,
+    /// Ingesting an external SST.
+    Ingest,
+}
+
+impl Point {
+    pub const ALL: [Self; 4] = [Self::Write, Self::Read, Self::Sync, Self::Ingest];
+}
+
+impl FromStr for Point {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "write" => Ok(Self::Write),
+            "read" => Ok(Self::Read),
+            "sync" => Ok(Self::Sync),
+            "ingest" => Ok(Self::Ingest),
+            s => Err(format!("Unexpected fault point: '{s}'. Expected write, read, sync, or ingest.")),
+        }
+    }
+}
+
+impl Display for Point {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Write => write!(f, "write"),
+            Self::Read => write!(f, "read"),
+            Self::Sync => write!(f, "sync"),
+            Self::Ingest => write!(f, "ingest"),
+        }
+    }
+}
+
+/// Which calls to one point fail, counting calls from 1.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Schedule {
+    #[default]
+    Never,
+    /// Each call fails with this probability.
+    Probability(f64),
+    /// Every `n`-th call fails.
+    Every(u64),
+    /// Every call after the first `n` fails, like a disk that has filled up.
+    After(u64),
+}
+
+impl Schedule {
+    fn fails(&self, call: u64) -> bool {
+        match *self {
+            Self::Never => false,
+            Self::Probability(probability) => thread_rng().gen_bool(probability),
+            Self::Every(n) => call.is_multiple_of(n),
+            Self::After(n) => call > n,
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("Unexpected fault schedule: '{s}'. Expected a probability, every:N, or after:N.");
+        match s.split_once(':') {
+            Some(("every", n)) => n.parse().ok().filter(|&n| n > 0).map(Self::Every).ok_or_else(err),
+            Some(("after", n)) => n.parse().map(Self::After).map_err(|_| err()),
+            Some(_) => Err(err()),
+            None => s.parse().ok().filter(|p| (0.0..=1.0).contains(p)).map(Self::Probability).ok_or_else(err),
+        }
+    }
+}
+
+impl Display for Schedule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Never => write!(f, "0"),
+            Self::Probability(probability) => write!(f, "{probability}"),
+            Self::Every(n) => write!(f, "every:{n}"),
+            Self::After(n) => write!(f, "after:{n}"),
+        }
+    }
+}
+
+/// Fails storage calls on a schedule per point, given as e.g. `write=0.01,read=every:100,sync=after:5000`. The
+/// default injects nothing.
+///
+/// Stores call [`Faults::check`] before each call to the underlying database, and turn the fault into whatever their
+/// error path is for a real failure of that call.
+#[derive(Debug, Default)]
+pub struct Faults {
+    schedules: [Schedule; 4],
+    calls: [AtomicU64; 4],
+    injected: [AtomicU64; 4],
+}
+
+impl Faults {
+    /// Counts a call to `point` and fails it if the schedule says so.
+    pub fn check(&self, point: Point) -> Result<(), Fault> {
+        let index = point as usize;
+        if self.schedules[index] == Schedule::Never {
+            return Ok(());
+        }
+        let call = self.calls[index].fetch_add(1, Ordering::Relaxed) + 1;
+        if self.schedules[index].fails(call) {
+            self.injected[index].fetch_add(1, Ordering::Relaxed);
+            Err(Fault { point, call })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Like [`Faults::check`], but unwinds with the [`Fault`] as the panic payload, for stores whose error path for
+    /// that call is a panic. Callers recover the fault with [`catch`].
+    pub fn inject(&self, point: Point) {
+        if let Err(fault) = self.check(point) {
+            panic::panic_any(fault);
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.schedules.iter().any(|schedule| *schedule != Schedule::Never)
+    }
+
+    /// How many faults have been injected into calls to `point` so far.
+    pub fn injected(&self, point: Point) -> u64 {
+        self.injected[point as usize].load(Ordering::Relaxed)
+    }
+
+    /// The calls and injected faults of every point with a schedule, e.g. `write 12/1000 (0.01)`.
+    pub fn summary(&self) -> String {
+        Point::ALL
+            .into_iter()
+            .filter(|&point| self.schedules[point as usize] != Schedule::Never)
+            .map(|point| {
+                let index = point as usize;
+                let calls = self.calls[index].load(Ordering::Relaxed);
+                format!("{point} {}/{calls} ({})", self.injected(point), self.schedules[index])
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// A clone starts from the same counts, and counts on separately from then on.
+impl Clone for Faults {
+    fn clone(&self) -> Self {
+        let load =
+            |counters: &[AtomicU64; 4]| counters.each_ref().map(|count| AtomicU64::new(count.load(Ordering::Relaxed)));
+        Self { schedules: self.schedules, calls: load(&self.calls), injected: load(&self.injected) }
+    }
+}
+
+impl FromStr for Faults {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut faults = Self::default();
+        for entry in s.split(',').filter(|entry| !entry.is_empty()) {
+            let (point, schedule) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("Unexpected fault: '{entry}'. Expected POINT=SCHEDULE, e.g. write=0.01."))?;
+            faults.schedules[point.parse::<Point>()? as usize] = schedule.parse()?;
+        }
+        Ok(faults)
+    }
+}
+
+/// A failure injected into a storage call.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Fault {
+    pub point: Point,
+    /// Which call to `point` failed, counting from 1.
+    pub call: u64,
+}
+
+impl Display for Fault {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "injected fault in {} call #{}", self.point, self.call)
+    }
+}
+
+impl Error for Fault {}
+
+/// The fault that a panic unwound with, if it was injected by [`Faults::inject`].
+pub fn fault_of(payload: &(dyn Any + Send)) -> Option<Fault> {
+    payload.downcast_ref::<Fault>().copied()
+}
+
+/// Runs `call`, returning the fault it unwound with if [`Faults::inject`] failed it. Other panics carry on unwinding.
+pub fn catch<T>(call: impl FnOnce() -> T) -> Result<T, Fault> {
+    panic::catch_unwind(panic::AssertUnwindSafe(call))
+        .map_err(|payload| fault_of(&*payload).unwrap_or_else(|| panic::resume_unwind(payload)))
+}
+
+/// Replaces the panic hook so that injected faults print as one line, or nothing if `quiet`. Other panics still go
+/// to the previous hook.
+pub fn set_panic_hook(quiet: bool) {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| match fault_of(info.payload()) {
+        Some(_) if quiet => (),
+        Some(fault) => eprintln!("{fault} at {}", info.location().map_or("unknown".to_owned(), ToString::to_string)),
+        None => previous(info),
+    }));
+}
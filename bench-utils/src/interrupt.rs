@@ -0,0 +1,61 @@
+use std::{
+    fs,
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often `sleep` checks whether it has been interrupted.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How many times SIGINT or SIGTERM has been received since `install_handler`.
+static SIGNALS: AtomicUsize = AtomicUsize::new(0);
+
+/// Makes SIGINT (Ctrl-C) and SIGTERM mark the process as interrupted instead of killing it, so that a benchmark can
+/// stop at the next convenient point and still report what it measured. A second signal exits straight away.
+pub fn install_handler() {
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        // SAFETY: the handler is async-signal-safe: it only touches an atomic and calls write and _exit
+        unsafe { libc::signal(signal, on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t) };
+    }
+}
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    let message: &[u8] = if SIGNALS.fetch_add(1, Ordering::SeqCst) == 0 {
+        b"\nInterrupted: stopping after the current operation, with partial results (again to exit now)\n"
+    } else {
+        // SAFETY: _exit is async-signal-safe, unlike exit
+        unsafe { libc::_exit(128 + signal) }
+    };
+    // SAFETY: the message is a static buffer of the given length
+    unsafe { libc::write(libc::STDERR_FILENO, message.as_ptr().cast(), message.len()) };
+}
+
+/// Whether SIGINT or SIGTERM has been received.
+pub fn interrupted() -> bool {
+    SIGNALS.load(Ordering::SeqCst) > 0
+}
+
+/// Sleeps for `duration`, or until interrupted. Returns whether the whole duration passed.
+pub fn sleep(duration: Duration) -> bool {
+    let start = Instant::now();
+    while !interrupted() {
+        match duration.checked_sub(start.elapsed()) {
+            Some(remaining) if !remaining.is_zero() => thread::sleep(remaining.min(POLL_INTERVAL)),
+            _ => return true,
+        }
+    }
+    false
+}
+
+/// Removes the store at `storage_dir` if the run was interrupted: it holds neither a fresh start nor a finished
+/// workload, so it isn't kept around.
+pub fn remove_interrupted_store(storage_dir: &Path) {
+    if interrupted() && storage_dir.exists() {
+        match fs::remove_dir_all(storage_dir) {
+            Ok(()) => println!("Removed the store of the interrupted run at {}", storage_dir.display()),
+            Err(err) => eprintln!("Could not remove {}: {err}", storage_dir.display()),
+        }
+    }
+}
@@ -0,0 +1,17 @@
+//! Measurement helpers shared by the benchmarks in this repository.
+//!
+//! Every benchmark run through [`trials::Trials`] appends its results to the JSON Lines file named by the
+//! `BENCH_RESULTS` environment variable, if set. The `compare` binary diffs two such files.
+
+pub mod affinity;
+pub mod disk;
+pub mod faults;
+pub mod interrupt;
+pub mod memory;
+pub mod progress;
+pub mod record;
+pub mod stats;
+pub mod sweep;
+pub mod trials;
+pub mod units;
+pub mod warmup;
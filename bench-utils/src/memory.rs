@@ -0,0 +1,144 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::units::Bytes;
+
+/// How often memory is sampled while a trial runs.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Memory usage over the course of one trial: a time series of named gauges, all in bytes.
+///
+/// `rss` is the resident set size of the process and `heap` the bytes allocated through malloc, as far as the allocator
+/// tells; with the `jemalloc` feature, `heap resident` adds the bytes of the pages jemalloc holds. Benchmarks add their
+/// own gauges, like the memtables and block cache of a store.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MemoryProfile {
+    /// Seconds since the trial started, for each sample.
+    pub seconds: Vec<f64>,
+    /// One value per sample for every gauge.
+    pub gauges: BTreeMap<String, Vec<u64>>,
+}
+
+impl MemoryProfile {
+    pub fn peak(&self, gauge: &str) -> Option<u64> {
+        self.gauges.get(gauge)?.iter().copied().max()
+    }
+
+    /// The peak of every gauge, in gauge order.
+    pub fn peaks(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.gauges.iter().filter_map(|(gauge, values)| Some((gauge.as_str(), values.iter().copied().max()?)))
+    }
+
+    fn sample(&mut self, at: Duration, gauges: Vec<(&'static str, u64)>) {
+        let sample = self.seconds.len();
+        self.seconds.push(at.as_secs_f64());
+        for (gauge, value) in gauges {
+            // a gauge that shows up late is zero-filled back to the start, so that every series lines up
+            self.gauges.entry(gauge.to_owned()).or_insert_with(|| vec![0; sample]).push(value);
+        }
+        self.gauges.values_mut().for_each(|values| values.resize(sample + 1, 0));
+    }
+}
+
+/// Runs `work` while sampling the memory of the process, along with the extra `gauges` of the benchmark, in a
+/// background thread. There is always one sample at the start and one at the end.
+pub fn track<T>(gauges: impl Fn() -> Vec<(&'static str, u64)> + Sync, work: impl FnOnce() -> T) -> (T, MemoryProfile) {
+    let start = Instant::now();
+    let sample_all = || process_gauges().into_iter().chain(gauges()).collect::<Vec<_>>();
+    thread::scope(|s| {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let sampler = s.spawn(move || {
+            let mut profile = MemoryProfile::default();
+            loop {
+                profile.sample(start.elapsed(), sample_all());
+                match stopped.recv_timeout(SAMPLE_INTERVAL) {
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    _ => break,
+                }
+            }
+            profile.sample(start.elapsed(), sample_all());
+            profile
+        });
+        let result = work();
+        drop(stop);
+        (result, sampler.join().unwrap())
+    })
+}
+
+/// The peak of every gauge over all the given profiles.
+pub fn peaks<'a>(profiles: impl IntoIterator<Item = &'a MemoryProfile>) -> BTreeMap<&'a str, u64> {
+    let mut peaks = BTreeMap::<&str, u64>::new();
+    for (gauge, peak) in profiles.into_iter().flat_map(MemoryProfile::peaks) {
+        let max = peaks.entry(gauge).or_default();
+        *max = (*max).max(peak);
+    }
+    peaks
+}
+
+/// Prints the peak of every gauge, over all the given profiles.
+pub fn print_peaks<'a>(profiles: impl IntoIterator<Item = &'a MemoryProfile>) {
+    let peaks = peaks(profiles);
+    if !peaks.is_empty() {
+        let peaks = peaks.into_iter().map(|(gauge, peak)| format!("{gauge} {}", Bytes(peak)));
+        println!("Peak memory: {}", peaks.collect::<Vec<_>>().join(", "));
+    }
+}
+
+/// The gauges every process has, where the platform provides them.
+fn process_gauges() -> Vec<(&'static str, u64)> {
+    [("rss", rss()), ("heap", heap()), ("heap resident", heap_resident())]
+        .into_iter()
+        .filter_map(|(gauge, value)| Some((gauge, value?)))
+        .collect()
+}
+
+/// The resident set size, from `/proc/self/statm`.
+pub fn rss() -> Option<u64> {
+    let statm = fs::read_to_string("/proc/self/statm").ok()?;
+    let resident_pages = statm.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    // SAFETY: sysconf has no preconditions
+    let page_size = u64::try_from(unsafe { libc::sysconf(libc::_SC_PAGESIZE) }).ok()?;
+    Some(resident_pages * page_size)
+}
+
+/// The bytes allocated through jemalloc, which takes the place of malloc for the whole process when it is linked with
+/// unprefixed symbols, as speedb's `jemalloc` feature does.
+#[cfg(feature = "jemalloc")]
+pub fn heap() -> Option<u64> {
+    // the statistics are a snapshot taken when the epoch last advanced
+    tikv_jemalloc_ctl::epoch::advance().ok()?;
+    tikv_jemalloc_ctl::stats::allocated::read().ok().map(|bytes| bytes as u64)
+}
+
+/// The bytes of the pages that jemalloc holds resident, including its metadata and dirty pages it has yet to return.
+#[cfg(feature = "jemalloc")]
+pub fn heap_resident() -> Option<u64> {
+    tikv_jemalloc_ctl::epoch::advance().ok()?;
+    tikv_jemalloc_ctl::stats::resident::read().ok().map(|bytes| bytes as u64)
+}
+
+/// The bytes in use by glibc's malloc. Allocators that replace malloc without the `jemalloc` feature don't show up
+/// here.
+#[cfg(all(not(feature = "jemalloc"), target_os = "linux", target_env = "gnu"))]
+pub fn heap() -> Option<u64> {
+    // SAFETY: mallinfo2 has no preconditions
+    let info = unsafe { libc::mallinfo2() };
+    Some((info.uordblks + info.hblkhd) as u64)
+}
+
+#[cfg(not(any(feature = "jemalloc", all(target_os = "linux", target_env = "gnu"))))]
+pub fn heap() -> Option<u64> {
+    None
+}
+
+#[cfg(not(feature = "jemalloc"))]
+pub fn heap_resident() -> Option<u64> {
+    None
+}
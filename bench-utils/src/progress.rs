@@ -0,0 +1,195 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{self, IsTerminal},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        mpsc::{self, RecvTimeoutError},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often the status line is redrawn.
+const INTERVAL: Duration = Duration::from_secs(1);
+/// Latencies are bucketed by their power of two in nanoseconds, with four buckets to each power of two.
+const BUCKETS: usize = 252;
+
+/// Runs `work` while redrawing a one-line status on stderr every second, from `status` given the time since the
+/// previous line. The line is cleared once `work` returns.
+///
+/// Unless stderr is a terminal, this only runs `work`, so that redirected output stays free of control characters.
+pub fn track<T>(mut status: impl FnMut(Duration) -> String + Send, work: impl FnOnce() -> T) -> T {
+    if !io::stderr().is_terminal() {
+        return work();
+    }
+    let start = Instant::now();
+    thread::scope(|s| {
+        let (stop, stopped) = mpsc::channel::<()>();
+        s.spawn(move || {
+            let mut previous = start;
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(INTERVAL) {
+                let now = Instant::now();
+                let line = format!("[{}s] {}", (now - start).as_secs(), status(now - previous));
+                previous = now;
+                // a line that wraps could not be redrawn in place
+                let line = line.chars().take(terminal_width().saturating_sub(1)).collect::<String>();
+                eprint!("\r{line}\x1b[K");
+            }
+            eprint!("\r\x1b[K");
+        });
+        let result = work();
+        drop(stop);
+        result
+    })
+}
+
+fn terminal_width() -> usize {
+    // SAFETY: TIOCGWINSZ only writes a winsize into the given one
+    let mut size = unsafe { std::mem::zeroed::<libc::winsize>() };
+    match unsafe { libc::ioctl(libc::STDERR_FILENO, libc::TIOCGWINSZ, &mut size) } {
+        0 if size.ws_col > 0 => size.ws_col as usize,
+        _ => 80,
+    }
+}
+
+/// The per-second rate of a counter between consecutive status lines.
+#[derive(Debug)]
+pub struct Rate<'a> {
+    counter: &'a AtomicUsize,
+    previous: usize,
+}
+
+impl<'a> Rate<'a> {
+    pub fn new(counter: &'a AtomicUsize) -> Self {
+        Self { counter, previous: counter.load(Ordering::Relaxed) }
+    }
+
+    /// The rate since the previous call, which was `interval` ago.
+    pub fn per_sec(&mut self, interval: Duration) -> f64 {
+        let current = self.counter.load(Ordering::Relaxed);
+        let rate = current.saturating_sub(self.previous) as f64 / interval.as_secs_f64();
+        self.previous = current;
+        rate
+    }
+}
+
+/// Counts the threads that are running a part of the workload.
+#[derive(Debug, Default)]
+pub struct ActiveThreads(AtomicUsize);
+
+impl ActiveThreads {
+    /// Counts the calling thread as active until the returned guard is dropped.
+    pub fn enter(&self) -> ActiveGuard<'_> {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        ActiveGuard(&self.0)
+    }
+
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug)]
+pub struct ActiveGuard<'a>(&'a AtomicUsize);
+
+impl Drop for ActiveGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Latencies recorded since they were last taken. Buckets are a quarter of a power of two wide, so percentiles are
+/// within 25%, and recording is one atomic increment, cheap enough to leave on in measured runs.
+#[derive(Debug)]
+pub struct LatencyHistogram {
+    buckets: [AtomicU64; BUCKETS],
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self { buckets: std::array::from_fn(|_| AtomicU64::new(0)) }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn record(&self, latency: Duration) {
+        let nanos = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX);
+        self.buckets[bucket(nanos)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Empties the histogram, returning what it held.
+    pub fn take(&self) -> LatencyCounts {
+        LatencyCounts { counts: self.buckets.each_ref().map(|bucket| bucket.swap(0, Ordering::Relaxed)) }
+    }
+}
+
+fn bucket(nanos: u64) -> usize {
+    if nanos < 4 {
+        return nanos as usize;
+    }
+    let exponent = 63 - nanos.leading_zeros() as usize;
+    4 * (exponent - 1) + ((nanos >> (exponent - 2)) & 3) as usize
+}
+
+/// The smallest latency in nanoseconds that falls into `bucket`.
+fn bucket_floor(bucket: usize) -> u64 {
+    if bucket < 4 {
+        return bucket as u64;
+    }
+    (4 + bucket as u64 % 4) << (bucket / 4 - 1)
+}
+
+/// The latencies taken out of a [`LatencyHistogram`]. Displays as the median and 99th percentile.
+#[derive(Clone, Debug)]
+pub struct LatencyCounts {
+    counts: [u64; BUCKETS],
+}
+
+impl LatencyCounts {
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// The lower bound of the bucket holding the `quantile` (between 0 and 1), or `None` if nothing was recorded.
+    pub fn percentile(&self, quantile: f64) -> Option<Duration> {
+        let rank = ((self.count() as f64 * quantile).ceil() as u64).max(1);
+        let mut seen = 0;
+        self.counts
+            .iter()
+            .position(|&count| {
+                seen += count;
+                seen >= rank
+            })
+            .map(|bucket| Duration::from_nanos(bucket_floor(bucket)))
+    }
+}
+
+impl Display for LatencyCounts {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match (self.percentile(0.5), self.percentile(0.99)) {
+            (Some(p50), Some(p99)) => write!(f, "p50 {p50:.1?}, p99 {p99:.1?}"),
+            _ => write!(f, "p50 -, p99 -"),
+        }
+    }
+}
+
+/// Whether a store is holding writes back until compaction catches up.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stall {
+    #[default]
+    None,
+    /// Writes are slowed down.
+    Delayed,
+    /// Writes are blocked.
+    Stopped,
+}
+
+impl Display for Stall {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Delayed => write!(f, "delayed"),
+            Self::Stopped => write!(f, "STOPPED"),
+        }
+    }
+}
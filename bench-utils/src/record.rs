@@ -0,0 +1,85 @@
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::memory::MemoryProfile;
+
+/// The environment variable naming the file that results are appended to.
+pub const RESULTS_ENV: &str = "BENCH_RESULTS";
+
+/// The results of all trials of one benchmark case, as stored on disk.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Record {
+    pub benchmark: String,
+    pub case: String,
+    pub params: BTreeMap<String, String>,
+    pub unit: String,
+    /// True for throughputs, false for latencies and other costs.
+    pub higher_is_better: bool,
+    pub samples: Vec<f64>,
+    /// The memory profile of each trial, for benchmarks that track memory.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub memory: Vec<MemoryProfile>,
+    /// Seconds since the Unix epoch when the record was made.
+    pub timestamp: u64,
+}
+
+/// What a record is compared by: the same benchmark, case, and parameters.
+pub type RecordKey = (String, String, BTreeMap<String, String>);
+
+impl Record {
+    pub fn new(
+        benchmark: &str,
+        case: &str,
+        params: BTreeMap<String, String>,
+        unit: &str,
+        higher_is_better: bool,
+        samples: Vec<f64>,
+    ) -> Self {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since_epoch| since_epoch.as_secs());
+        Self {
+            benchmark: benchmark.to_owned(),
+            case: case.to_owned(),
+            params,
+            unit: unit.to_owned(),
+            higher_is_better,
+            samples,
+            memory: Vec::new(),
+            timestamp,
+        }
+    }
+
+    pub fn key(&self) -> RecordKey {
+        (self.benchmark.clone(), self.case.clone(), self.params.clone())
+    }
+}
+
+/// The results file configured through `BENCH_RESULTS`, if any.
+pub fn results_path() -> Option<PathBuf> {
+    std::env::var_os(RESULTS_ENV).map(PathBuf::from)
+}
+
+/// Appends records to a JSON Lines file, one record per line, creating it if needed.
+pub fn append(path: &Path, records: &[Record]) -> io::Result<()> {
+    let mut out = BufWriter::new(OpenOptions::new().create(true).append(true).open(path)?);
+    for record in records {
+        serde_json::to_writer(&mut out, record)?;
+        writeln!(out)?;
+    }
+    out.flush()
+}
+
+/// Reads every record in a JSON Lines file, in the order they were appended.
+pub fn load(path: &Path) -> io::Result<Vec<Record>> {
+    BufReader::new(File::open(path)?)
+        .lines()
+        .filter(|line| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
@@ -0,0 +1,121 @@
+use std::fmt::{Display, Formatter};
+
+/// Two-sided 95% critical values of Student's t-distribution for 1 to 30 degrees of freedom.
+const T_95: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160, 2.145, 2.131, 2.120,
+    2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056, 2.052, 2.048, 2.045, 2.042,
+];
+
+/// The two-sided 95% critical value of Student's t-distribution. Fractional degrees of freedom are rounded down, which
+/// errs on the side of a wider interval.
+pub fn t_critical_95(degrees_of_freedom: f64) -> f64 {
+    match degrees_of_freedom.floor() as usize {
+        0 => f64::INFINITY,
+        df @ 1..=30 => T_95[df - 1],
+        31..=40 => 2.021,
+        41..=60 => 2.000,
+        61..=120 => 1.980,
+        _ => 1.960,
+    }
+}
+
+/// Descriptive statistics of the results of repeated trials.
+#[derive(Clone, Debug)]
+pub struct Summary {
+    pub samples: Vec<f64>,
+    pub mean: f64,
+    pub median: f64,
+    /// Sample standard deviation, zero for a single sample.
+    pub stddev: f64,
+    /// The 95% confidence interval of the mean, from the t-distribution.
+    pub confidence_interval: (f64, f64),
+    /// Indices of the samples outside Tukey's fences (1.5 interquartile ranges beyond the quartiles). Needs at least
+    /// four samples to flag anything.
+    pub outliers: Vec<usize>,
+}
+
+impl Summary {
+    pub fn new(samples: Vec<f64>) -> Self {
+        assert!(!samples.is_empty(), "cannot summarise zero samples");
+        let n = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / n;
+        let stddev = if samples.len() > 1 {
+            (samples.iter().map(|sample| (sample - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt()
+        } else {
+            0.0
+        };
+        let half_width = if samples.len() > 1 { t_critical_95(n - 1.0) * stddev / n.sqrt() } else { 0.0 };
+
+        let mut sorted = samples.clone();
+        sorted.sort_by(f64::total_cmp);
+        let median = quantile(&sorted, 0.5);
+        let outliers = if samples.len() >= 4 {
+            let (q1, q3) = (quantile(&sorted, 0.25), quantile(&sorted, 0.75));
+            let (low, high) = (q1 - 1.5 * (q3 - q1), q3 + 1.5 * (q3 - q1));
+            (0..samples.len()).filter(|&i| samples[i] < low || samples[i] > high).collect()
+        } else {
+            Vec::new()
+        };
+
+        Self { samples, mean, median, stddev, confidence_interval: (mean - half_width, mean + half_width), outliers }
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (low, high) = self.confidence_interval;
+        write!(
+            f,
+            "mean: {:.2} (95% CI {low:.2} .. {high:.2}), median: {:.2}, stddev: {:.2}, trials: {}",
+            self.mean,
+            self.median,
+            self.stddev,
+            self.samples.len(),
+        )?;
+        if !self.outliers.is_empty() {
+            let outliers = self.outliers.iter().map(|&i| format!("#{} ({:.2})", i + 1, self.samples[i]));
+            write!(f, ", outliers: {}", outliers.collect::<Vec<_>>().join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Linearly interpolated quantile of sorted samples.
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let position = (sorted.len() - 1) as f64 * q;
+    let (below, above) = (position.floor() as usize, position.ceil() as usize);
+    sorted[below] + (sorted[above] - sorted[below]) * (position - below as f64)
+}
+
+/// The outcome of Welch's t-test for a difference in means between two sets of samples with possibly unequal
+/// variances.
+#[derive(Copy, Clone, Debug)]
+pub struct WelchTest {
+    pub t: f64,
+    pub degrees_of_freedom: f64,
+    /// Whether the difference is significant at the 5% level (two-sided).
+    pub significant: bool,
+}
+
+impl WelchTest {
+    /// Needs at least two samples on each side; returns `None` otherwise.
+    pub fn new(baseline: &Summary, current: &Summary) -> Option<Self> {
+        let (n1, n2) = (baseline.samples.len() as f64, current.samples.len() as f64);
+        if n1 < 2.0 || n2 < 2.0 {
+            return None;
+        }
+        let (v1, v2) = (baseline.stddev.powi(2) / n1, current.stddev.powi(2) / n2);
+        if v1 + v2 == 0.0 {
+            // no variance on either side: any difference at all is real
+            let significant = baseline.mean != current.mean;
+            return Some(Self {
+                t: if significant { f64::INFINITY } else { 0.0 },
+                degrees_of_freedom: n1 + n2 - 2.0,
+                significant,
+            });
+        }
+        let t = (current.mean - baseline.mean) / (v1 + v2).sqrt();
+        let degrees_of_freedom = (v1 + v2).powi(2) / (v1.powi(2) / (n1 - 1.0) + v2.powi(2) / (n2 - 1.0));
+        Some(Self { t, degrees_of_freedom, significant: t.abs() > t_critical_95(degrees_of_freedom) })
+    }
+}
@@ -0,0 +1,89 @@
+use std::{collections::BTreeMap, str::FromStr};
+
+use crate::stats::Summary;
+
+/// A parameter sweep: named axes with the values to try on each, like `threads=1,4 mode=SINGLE,CF`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sweep {
+    pub axes: Vec<(String, Vec<String>)>,
+}
+
+impl FromStr for Sweep {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let axes = s
+            .split(|c: char| c.is_whitespace() || c == ';')
+            .filter(|axis| !axis.is_empty())
+            .map(|axis| match axis.split_once('=') {
+                Some((name, values)) if !name.is_empty() && !values.is_empty() => {
+                    Ok((name.to_owned(), values.split(',').map(str::to_owned).collect()))
+                }
+                _ => Err(format!("Unexpected sweep axis: '{axis}'. Expected name=value,value,...")),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { axes })
+    }
+}
+
+impl Sweep {
+    /// Checks that every axis is one of `known`.
+    pub fn check_axes(&self, known: &[&str]) -> Result<(), String> {
+        match self.axes.iter().find(|(name, _)| !known.contains(&name.as_str())) {
+            Some((name, _)) => Err(format!("Unknown sweep axis: '{name}'. Expected one of {}.", known.join(", "))),
+            None => Ok(()),
+        }
+    }
+
+    /// Every combination of one value per axis, varying the last axis fastest.
+    pub fn combinations(&self) -> Vec<BTreeMap<String, String>> {
+        self.axes.iter().fold(vec![BTreeMap::new()], |combinations, (name, values)| {
+            combinations
+                .into_iter()
+                .flat_map(|combination| {
+                    values.iter().map(move |value| {
+                        let mut combination = combination.clone();
+                        combination.insert(name.clone(), value.clone());
+                        combination
+                    })
+                })
+                .collect()
+        })
+    }
+
+    /// Prints one row per combination with its summary, with the axes as the leading columns.
+    pub fn print_table(&self, unit: &str, results: &[(BTreeMap<String, String>, Summary)]) {
+        let headers = self
+            .axes
+            .iter()
+            .map(|(name, _)| name.clone())
+            .chain([format!("mean [{unit}]"), "95% CI".to_owned(), "stddev".to_owned(), "outliers".to_owned()])
+            .collect::<Vec<_>>();
+        let rows = results
+            .iter()
+            .map(|(combination, summary)| {
+                let (low, high) = summary.confidence_interval;
+                self.axes
+                    .iter()
+                    .map(|(name, _)| combination[name].clone())
+                    .chain([
+                        format!("{:.2}", summary.mean),
+                        format!("{low:.2} .. {high:.2}"),
+                        format!("{:.2}", summary.stddev),
+                        summary.outliers.len().to_string(),
+                    ])
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let widths = (0..headers.len())
+            .map(|column| rows.iter().map(|row| row[column].len()).chain([headers[column].len()]).max().unwrap())
+            .collect::<Vec<_>>();
+        let print_row = |row: &[String]| {
+            let cells = row.iter().zip(&widths).map(|(cell, &width)| format!("{cell:>width$}"));
+            println!("| {} |", cells.collect::<Vec<_>>().join(" | "));
+        };
+        print_row(&headers);
+        println!("|{}|", widths.iter().map(|&width| "-".repeat(width + 2)).collect::<Vec<_>>().join("|"));
+        rows.iter().for_each(|row| print_row(row));
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+
+use rand::{seq::SliceRandom, thread_rng};
+
+use crate::{
+    interrupt,
+    memory::{self, MemoryProfile},
+    record::{self, Record},
+    stats::Summary,
+};
+
+/// The outcome of one trial: its measurement, and how much memory it took if that was tracked.
+#[derive(Clone, Debug)]
+pub struct Trial {
+    pub value: f64,
+    pub memory: Option<MemoryProfile>,
+}
+
+impl From<f64> for Trial {
+    fn from(value: f64) -> Self {
+        Self { value, memory: None }
+    }
+}
+
+impl From<(f64, MemoryProfile)> for Trial {
+    fn from((value, memory): (f64, MemoryProfile)) -> Self {
+        Self { value, memory: Some(memory) }
+    }
+}
+
+struct Case<'a> {
+    name: String,
+    params: BTreeMap<String, String>,
+    unit: &'static str,
+    run: Box<dyn FnMut() -> Trial + 'a>,
+}
+
+/// Runs every benchmark case a number of times and summarises the result each trial returned.
+///
+/// With shuffling, the trials of all cases are run in a random order instead of case by case, so that drift over the
+/// course of a run (thermal throttling, a filling disk, background jobs) doesn't line up with any one case.
+pub struct Trials<'a> {
+    benchmark: &'static str,
+    trials: usize,
+    shuffle: bool,
+    cases: Vec<Case<'a>>,
+}
+
+impl<'a> Trials<'a> {
+    pub fn new(benchmark: &'static str, trials: usize) -> Self {
+        assert!(trials > 0, "need at least one trial");
+        Self { benchmark, trials, shuffle: false, cases: Vec::new() }
+    }
+
+    pub fn shuffled(self, shuffle: bool) -> Self {
+        Self { shuffle, ..self }
+    }
+
+    /// Adds a case whose trials each return one measurement in `unit`, where higher is better, optionally with the
+    /// memory profile of the trial (see `memory::track`).
+    pub fn case<T: Into<Trial>>(
+        &mut self,
+        name: impl Into<String>,
+        unit: &'static str,
+        run: impl FnMut() -> T + 'a,
+    ) -> &mut Self {
+        self.case_with_params(name, [], unit, run)
+    }
+
+    /// Like `case`, with the parameters that the case is recorded under, to tell apart results of the same case.
+    pub fn case_with_params<T: Into<Trial>>(
+        &mut self,
+        name: impl Into<String>,
+        params: impl IntoIterator<Item = (&'static str, String)>,
+        unit: &'static str,
+        mut run: impl FnMut() -> T + 'a,
+    ) -> &mut Self {
+        let params = params.into_iter().map(|(key, value)| (key.to_owned(), value)).collect();
+        self.cases.push(Case { name: name.into(), params, unit, run: Box::new(move || run().into()) });
+        self
+    }
+
+    /// Runs all trials, printing a summary of each case at the end, and returns the summaries in the order the cases
+    /// were added. The results are also appended to the file named by `BENCH_RESULTS`, if set.
+    ///
+    /// Once interrupted (see `interrupt::install_handler`), no more trials are started and the one in progress is
+    /// discarded, as it was cut short. Cases that didn't get to finish any have no summary, and nothing is appended to
+    /// `BENCH_RESULTS`, so that a partial run doesn't pass for a complete one.
+    pub fn run(mut self) -> Vec<(String, Option<Summary>)> {
+        let mut order = (0..self.cases.len()).flat_map(|case| (0..self.trials).map(move |_| case)).collect::<Vec<_>>();
+        if self.shuffle {
+            order.shuffle(&mut thread_rng());
+        }
+
+        let mut samples = vec![Vec::with_capacity(self.trials); self.cases.len()];
+        let mut memory = vec![Vec::new(); self.cases.len()];
+        for (started, &case) in order.iter().enumerate() {
+            if interrupt::interrupted() {
+                println!("# Interrupted, skipping the remaining {} trials", order.len() - started);
+                break;
+            }
+            let Case { name, run, .. } = &mut self.cases[case];
+            println!("# {name} (trial {} of {})", samples[case].len() + 1, self.trials);
+            let trial = run();
+            if interrupt::interrupted() {
+                println!(
+                    "# Interrupted, discarding this trial and skipping the remaining {}",
+                    order.len() - started - 1
+                );
+                break;
+            }
+            samples[case].push(trial.value);
+            memory[case].extend(trial.memory);
+        }
+
+        println!("# Summary");
+        let mut records = Vec::new();
+        let summaries = self
+            .cases
+            .into_iter()
+            .zip(samples)
+            .zip(memory)
+            .map(|((Case { name, params, unit, .. }, samples), memory)| {
+                if samples.is_empty() {
+                    println!("{name} [{unit}]: not run");
+                    return (name, None);
+                }
+                let summary = Summary::new(samples);
+                println!("{name} [{unit}]: {summary}");
+                memory::print_peaks(&memory);
+                let mut record = Record::new(self.benchmark, &name, params, unit, true, summary.samples.clone());
+                record.memory = memory;
+                records.push(record);
+                (name, Some(summary))
+            })
+            .collect();
+
+        if interrupt::interrupted() {
+            println!("Not saving the results of an interrupted run");
+        } else if let Some(path) = record::results_path() {
+            match record::append(&path, &records) {
+                Ok(()) => println!("Saved {} records to {}", records.len(), path.display()),
+                Err(err) => eprintln!("Could not save results to {}: {err}", path.display()),
+            }
+        }
+        summaries
+    }
+}
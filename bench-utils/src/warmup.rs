@@ -0,0 +1,88 @@
+use std::{
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Length of the windows that throughput is sampled over while waiting for it to settle.
+const WINDOW: Duration = Duration::from_secs(1);
+/// How many consecutive windows have to agree before throughput counts as steady.
+const STABLE_WINDOWS: usize = 3;
+/// How far apart the slowest and fastest of those windows may be, relative to their mean.
+const TOLERANCE: f64 = 0.1;
+/// Automatic warm-up gives up after this long, so that a workload that never settles still gets measured.
+const MAX_AUTO_WARMUP: Duration = Duration::from_secs(120);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Warmup {
+    Fixed(Duration),
+    Auto,
+}
+
+impl FromStr for Warmup {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            s => match s.parse::<f64>() {
+                Ok(seconds) if seconds >= 0.0 => Ok(Self::Fixed(Duration::from_secs_f64(seconds))),
+                _ => Err(format!("Unexpected warm-up: '{s}'. Expected a number of seconds or auto.")),
+            },
+        }
+    }
+}
+
+/// Where the warm-up ended: the measured window starts at `at`, with `progress` operations already done.
+#[derive(Copy, Clone, Debug)]
+pub struct WarmupEnd {
+    pub at: Instant,
+    pub elapsed: Duration,
+    pub progress: usize,
+    /// Whether throughput settled. Only ever false for automatic warm-ups that timed out or ran out of work.
+    pub steady: bool,
+}
+
+impl Warmup {
+    /// Blocks until the warm-up is over, watching `progress` (a count of completed operations) and returning early if
+    /// `done` says that the workload has finished.
+    pub fn wait(self, progress: &AtomicUsize, done: impl Fn() -> bool) -> WarmupEnd {
+        let start = Instant::now();
+        let end = |steady| {
+            let at = Instant::now();
+            WarmupEnd { at, elapsed: at - start, progress: progress.load(Ordering::Relaxed), steady }
+        };
+        match self {
+            Self::Fixed(duration) => {
+                while !done() {
+                    match duration.checked_sub(start.elapsed()) {
+                        Some(remaining) if !remaining.is_zero() => thread::sleep(remaining.min(WINDOW)),
+                        _ => break,
+                    }
+                }
+                end(true)
+            }
+            Self::Auto => {
+                let mut rates = Vec::new();
+                let mut previous = progress.load(Ordering::Relaxed);
+                while !done() && start.elapsed() < MAX_AUTO_WARMUP {
+                    thread::sleep(WINDOW);
+                    let current = progress.load(Ordering::Relaxed);
+                    rates.push((current - previous) as f64 / WINDOW.as_secs_f64());
+                    previous = current;
+                    if is_steady(&rates) {
+                        return end(true);
+                    }
+                }
+                end(false)
+            }
+        }
+    }
+}
+
+fn is_steady(rates: &[f64]) -> bool {
+    let Some(recent) = rates.len().checked_sub(STABLE_WINDOWS).map(|start| &rates[start..]) else { return false };
+    let mean = recent.iter().sum::<f64>() / recent.len() as f64;
+    let (min, max) = recent.iter().fold((f64::MAX, f64::MIN), |(min, max), &rate| (min.min(rate), max.max(rate)));
+    mean > 0.0 && (max - min) / mean <= TOLERANCE
+}
@@ -1,5 +1,11 @@
 use std::{collections::BTreeSet, sync::Mutex, time::Instant};
 
+use bench_utils::{
+    affinity::Placement,
+    memory,
+    trials::{Trial, Trials},
+};
+use clap::{arg, command, value_parser, ArgAction};
 use crossbeam_skiplist::SkipSet;
 use crossbeam_utils::thread::scope;
 use rand::{thread_rng, Rng};
@@ -8,6 +14,28 @@ type Key = [u8; 32];
 const KEY_SIZE: usize = std::mem::size_of::<Key>();
 
 fn main() {
+    let args = command!()
+        .arg(
+            arg!(--pin <PLACEMENT> "os / round-robin / compact / CPU list (e.g. 0,2,4-7) to pin the inserters to")
+                .value_parser(value_parser!(Placement))
+                .default_value("os"),
+        )
+        .arg(
+            arg!(--trials <TRIALS> "how many times to run each case")
+                .value_parser(value_parser!(usize))
+                .default_value("1"),
+        )
+        .arg(
+            arg!(--shuffle "interleave the trials of all cases, so that drift over the run doesn't favour the \
+                 earlier ones")
+            .action(ArgAction::SetTrue),
+        )
+        .get_matches();
+    let placement = args.get_one::<Placement>("pin").unwrap().plan().expect("could not plan thread placement");
+    let pin = args.get_raw("pin").unwrap().map(|value| value.to_string_lossy()).collect::<Vec<_>>().join(",");
+    let params = || [("pin", pin.clone())];
+    println!("{}", placement.describe(&[("concurrent inserters", 0..4)]));
+
     let total_size_mb = 1024usize;
     println!("Key size: {} bytes", KEY_SIZE);
     println!("Total generated size: {} MiB\n", total_size_mb);
@@ -19,151 +47,180 @@ fn main() {
     let data: Vec<Key> = (0..num_keys).map(|_| random()).collect();
     report_throughput(total_size_mb, now);
 
-    println!("# Vec: pushes, then sort");
-    measure(&data, |data| {
-        let mut vec = Vec::new();
-        for &x in data {
-            vec.push(x);
-        }
-        vec.sort();
+    let mut trials =
+        Trials::new(env!("CARGO_PKG_NAME"), *args.get_one("trials").unwrap()).shuffled(args.get_flag("shuffle"));
+    trials.case_with_params("Vec: pushes, then sort", params(), "MiB/s", || {
+        measure(&data, |data| {
+            let mut vec = Vec::new();
+            for &x in data {
+                vec.push(x);
+            }
+            vec.sort();
+        })
     });
 
-    println!("# Vec: pushes with_capacity, then sort");
-    measure(&data, |data| {
-        let mut vec = Vec::with_capacity(num_keys);
-        for &x in data {
-            vec.push(x);
-        }
-        vec.sort();
+    trials.case_with_params("Vec: pushes with_capacity, then sort", params(), "MiB/s", || {
+        measure(&data, |data| {
+            let mut vec = Vec::with_capacity(num_keys);
+            for &x in data {
+                vec.push(x);
+            }
+            vec.sort();
+        })
     });
 
-    println!("# Vec: collect, then sort");
-    measure(&data, |data| {
-        let mut vec: Vec<Key> = std::hint::black_box(data.iter().copied().collect());
-        vec.sort();
+    trials.case_with_params("Vec: collect, then sort", params(), "MiB/s", || {
+        measure(&data, |data| {
+            // collecting is what this case measures, as opposed to the copy of `to_vec`
+            #[allow(clippy::iter_cloned_collect)]
+            let mut vec: Vec<Key> = std::hint::black_box(data.iter().copied().collect());
+            vec.sort();
+        })
     });
 
-    println!("# BTreeSet: insert");
-    measure(&data, |data| {
-        let mut set = BTreeSet::new();
-        for &x in data {
-            set.insert(x);
-        }
+    trials.case_with_params("BTreeSet: insert", params(), "MiB/s", || {
+        measure(&data, |data| {
+            let mut set = BTreeSet::new();
+            for &x in data {
+                set.insert(x);
+            }
+        })
     });
 
-    println!("# BTreeSet: collect");
-    measure(&data, |data| {
-        let _set: BTreeSet<Key> = data.iter().copied().collect();
+    trials.case_with_params("BTreeSet: collect", params(), "MiB/s", || {
+        measure(&data, |data| {
+            let _set: BTreeSet<Key> = data.iter().copied().collect();
+        })
     });
 
-    println!("# BTreeSet: extend");
-    measure(&data, |data| {
-        let mut set = BTreeSet::new();
-        set.extend(data.iter().copied());
+    trials.case_with_params("BTreeSet: extend", params(), "MiB/s", || {
+        measure(&data, |data| {
+            let mut set = BTreeSet::new();
+            set.extend(data.iter().copied());
+        })
     });
 
-    println!("# BTreeSet: concurrent inserts in 4 threads");
-    measure(&data, |data| {
-        let set = Mutex::new(BTreeSet::new());
-        scope(|s| {
-            let set = &set;
-            for chunk in data.chunks(data.len() / 4) {
-                s.spawn(move |_| {
-                    for &x in chunk {
-                        set.lock().unwrap().insert(x);
-                    }
-                });
-            }
+    trials.case_with_params("BTreeSet: concurrent inserts in 4 threads", params(), "MiB/s", || {
+        measure(&data, |data| {
+            let set = Mutex::new(BTreeSet::new());
+            scope(|s| {
+                let (set, placement) = (&set, &placement);
+                for (i, chunk) in data.chunks(data.len() / 4).enumerate() {
+                    s.spawn(move |_| {
+                        placement.pin(i);
+                        for &x in chunk {
+                            set.lock().unwrap().insert(x);
+                        }
+                    });
+                }
+            })
+            .unwrap();
         })
-        .unwrap();
     });
 
-    println!("# BTreeSet: batched concurrent inserts in 4 threads");
-    measure(&data, |data| {
-        let set = Mutex::new(BTreeSet::new());
-        scope(|s| {
-            let set = &set;
-            for chunk in data.chunks(data.len() / 4) {
-                s.spawn(move |_| {
-                    for batch in chunk.chunks(32) {
-                        let mut set = set.lock().unwrap();
-                        for &x in batch {
-                            set.insert(x);
+    trials.case_with_params("BTreeSet: batched concurrent inserts in 4 threads", params(), "MiB/s", || {
+        measure(&data, |data| {
+            let set = Mutex::new(BTreeSet::new());
+            scope(|s| {
+                let (set, placement) = (&set, &placement);
+                for (i, chunk) in data.chunks(data.len() / 4).enumerate() {
+                    s.spawn(move |_| {
+                        placement.pin(i);
+                        for batch in chunk.chunks(32) {
+                            let mut set = set.lock().unwrap();
+                            for &x in batch {
+                                set.insert(x);
+                            }
                         }
-                    }
-                });
-            }
+                    });
+                }
+            })
+            .unwrap();
         })
-        .unwrap();
     });
 
-    println!("# SkipSet: insert");
-    measure(&data, |data| {
-        let set = SkipSet::new();
-        for &x in data {
-            set.insert(x);
-        }
+    trials.case_with_params("SkipSet: insert", params(), "MiB/s", || {
+        measure(&data, |data| {
+            let set = SkipSet::new();
+            for &x in data {
+                set.insert(x);
+            }
+        })
     });
 
-    println!("# SkipSet: collect");
-    measure(&data, |data| {
-        let _set: SkipSet<Key> = data.iter().copied().collect();
+    trials.case_with_params("SkipSet: collect", params(), "MiB/s", || {
+        measure(&data, |data| {
+            let _set: SkipSet<Key> = data.iter().copied().collect();
+        })
     });
 
-    println!("# SkipSet: sorted inserts");
-    measure(&data, |data| {
-        let btree_set: BTreeSet<Key> = data.iter().copied().collect();
-        let set = SkipSet::new();
-        for x in btree_set {
-            set.insert(x);
-        }
+    trials.case_with_params("SkipSet: sorted inserts", params(), "MiB/s", || {
+        measure(&data, |data| {
+            let btree_set: BTreeSet<Key> = data.iter().copied().collect();
+            let set = SkipSet::new();
+            for x in btree_set {
+                set.insert(x);
+            }
+        })
     });
 
-    println!("# SkipSet: concurrent inserts in 4 threads");
-    measure(&data, |data| {
-        let set = SkipSet::new();
-        scope(|s| {
-            let set = &set;
-            for chunk in data.chunks(data.len() / 4) {
-                s.spawn(move |_| {
-                    for &x in chunk {
-                        set.insert(x);
-                    }
-                });
-            }
+    trials.case_with_params("SkipSet: concurrent inserts in 4 threads", params(), "MiB/s", || {
+        measure(&data, |data| {
+            let set = SkipSet::new();
+            scope(|s| {
+                let (set, placement) = (&set, &placement);
+                for (i, chunk) in data.chunks(data.len() / 4).enumerate() {
+                    s.spawn(move |_| {
+                        placement.pin(i);
+                        for &x in chunk {
+                            set.insert(x);
+                        }
+                    });
+                }
+            })
+            .unwrap();
         })
-        .unwrap();
     });
 
-    println!("# SkipSet: concurrent sorted inserts in 4 threads");
-    measure(&data, |data| {
-        let set = SkipSet::new();
-        scope(|s| {
-            let set = &set;
-            for chunk in data.chunks(data.len() / 4) {
-                s.spawn(move |_| {
-                    let btree_set: BTreeSet<Key> = chunk.iter().copied().collect();
-                    for x in btree_set {
-                        set.insert(x);
-                    }
-                });
-            }
+    trials.case_with_params("SkipSet: concurrent sorted inserts in 4 threads", params(), "MiB/s", || {
+        measure(&data, |data| {
+            let set = SkipSet::new();
+            scope(|s| {
+                let (set, placement) = (&set, &placement);
+                for (i, chunk) in data.chunks(data.len() / 4).enumerate() {
+                    s.spawn(move |_| {
+                        placement.pin(i);
+                        let btree_set: BTreeSet<Key> = chunk.iter().copied().collect();
+                        for x in btree_set {
+                            set.insert(x);
+                        }
+                    });
+                }
+            })
+            .unwrap();
         })
-        .unwrap();
     });
+
+    trials.run();
 }
 
-fn measure(data: &Vec<Key>, f: impl FnOnce(&Vec<Key>)) {
-    let now = Instant::now();
-    f(data);
-    report_throughput(data.len() * KEY_SIZE / 1024 / 1024, now);
+/// Returns the throughput in MiB/s, with the memory used along the way. The keys themselves are part of it.
+fn measure(data: &[Key], f: impl Fn(&[Key])) -> Trial {
+    let (throughput, memory) = memory::track(Vec::new, || {
+        let now = Instant::now();
+        f(data);
+        report_throughput(data.len() * KEY_SIZE / 1024 / 1024, now)
+    });
+    Trial::from((throughput, memory))
 }
 
-fn report_throughput(size_mb: usize, now: Instant) {
+fn report_throughput(size_mb: usize, now: Instant) -> f64 {
     let elapsed = now.elapsed();
+    let throughput = size_mb as f64 / elapsed.as_secs_f64();
     println!("Done in {:.3} s", elapsed.as_secs_f64());
-    println!("Throughput: {:.2} MiB/s", size_mb as f64 / elapsed.as_secs_f64());
+    println!("Throughput: {throughput:.2} MiB/s");
     println!();
+    throughput
 }
 
 fn random() -> Key {
@@ -4,21 +4,40 @@ use std::{
     time::{Duration, Instant},
 };
 
+use bench_utils::trials::Trials;
+use clap::{arg, command, value_parser, ArgAction};
 use rand::{thread_rng, Rng};
 
 fn main() {
+    let args = command!()
+        .arg(
+            arg!(--trials <TRIALS> "how many times to run each case")
+                .value_parser(value_parser!(usize))
+                .default_value("1"),
+        )
+        .arg(
+            arg!(--shuffle "interleave the trials of all cases, so that drift over the run doesn't favour the \
+                 earlier ones")
+            .action(ArgAction::SetTrue),
+        )
+        .get_matches();
     let data = {
         let mut data = vec![0u8; 1024 * 1024 * 1024];
         thread_rng().fill(&mut *data);
         data
     };
+    let data = &data;
+    let mut trials =
+        Trials::new(env!("CARGO_PKG_NAME"), *args.get_one("trials").unwrap()).shuffled(args.get_flag("shuffle"));
     for sync_interval in [2, 10, 50, 100].into_iter().map(Duration::from_millis) {
-        println!("Syncing all metadata every {sync_interval:?}");
-        write_benchmark(&data, sync_interval);
+        trials.case(format!("Syncing all metadata every {sync_interval:?}"), "MiB/s", move || {
+            write_benchmark(data, sync_interval)
+        });
     }
+    trials.run();
 }
 
-fn write_benchmark(data: &[u8], _: Duration) {
+fn write_benchmark(data: &[u8], _: Duration) -> f64 {
     let mut file = File::create("./.tmp").expect("could not open ./.tmp for writing");
     let start = Instant::now();
     for chunk in data.chunks(4096) {
@@ -26,13 +45,16 @@ fn write_benchmark(data: &[u8], _: Duration) {
     }
     file.sync_all().expect("could not sync file to disk");
     drop(file);
-    report_throughput(data.len(), start);
+    let throughput = report_throughput(data.len(), start);
     fs::remove_file("./.tmp").expect("could not delete ./.tmp");
+    throughput
 }
 
-fn report_throughput(size: usize, now: Instant) {
+fn report_throughput(size: usize, now: Instant) -> f64 {
     let elapsed = now.elapsed();
+    let throughput = size as f64 / 1024.0 / 1024.0 / elapsed.as_secs_f64();
     println!("Done in {:.3} s", elapsed.as_secs_f64());
-    println!("Throughput: {:.2} MiB/s", size as f64 / 1024.0 / 1024.0 / elapsed.as_secs_f64());
+    println!("Throughput: {throughput:.2} MiB/s");
     println!();
+    throughput
 }
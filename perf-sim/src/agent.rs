@@ -1,63 +1,620 @@
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
 
+use bench_utils::{progress::LatencyHistogram, units::Bytes};
+use itertools::Itertools;
 use rand::{seq::SliceRandom, thread_rng, Rng};
 
 use crate::{
     concept::{Attribute, AttributeType, Prefix, Thing, ThingID, Type, TypeID, ValueType},
-    storage::{Storage, WriteHandle},
+    storage::{Durability, ErrorCounts, KeySpace, ReadSnapshot, Reads, Storage, StorageError, WriteHandle},
 };
 
 pub const PERSON: Type = Type { prefix: Prefix::Entity, id: TypeID { id: 0 } };
 pub const FRIENDSHIP: Type = Type { prefix: Prefix::Relation, id: TypeID { id: 0 } };
 pub const FRIEND: Type = Type { prefix: Prefix::Role, id: TypeID { id: 0 } };
+pub const GATHERING: Type = Type { prefix: Prefix::Relation, id: TypeID { id: 1 } };
+pub const HOST: Type = Type { prefix: Prefix::Role, id: TypeID { id: 1 } };
+pub const SPEAKER: Type = Type { prefix: Prefix::Role, id: TypeID { id: 2 } };
+pub const GUEST: Type = Type { prefix: Prefix::Role, id: TypeID { id: 3 } };
 pub const NAME: AttributeType =
     AttributeType { prefix: Prefix::Attribute, id: TypeID { id: 0 }, value_type: ValueType::Long };
+pub const AGE: AttributeType =
+    AttributeType { prefix: Prefix::Attribute, id: TypeID { id: 1 }, value_type: ValueType::Long };
+
+/// Every type that keys can lead with. Each gets its own column family in TYPE mode.
+pub const SCHEMA: [Type; 7] = [
+    PERSON,
+    FRIENDSHIP,
+    GATHERING,
+    Type { prefix: NAME.prefix, id: NAME.id },
+    Type { prefix: AGE.prefix, id: AGE.id },
+    Type { prefix: Prefix::AttributeIndex, id: NAME.id },
+    Type { prefix: Prefix::AttributeIndex, id: AGE.id },
+];
+
+/// Width of the age ranges that range readers query.
+const AGE_RANGE_WIDTH: u64 = 10;
+/// Persons that an ingest agent registers per commit.
+const INGEST_BATCH: usize = 10;
+/// Random persons that a preferential agent weighs up for every friendship it makes.
+const CANDIDATES_PER_ATTACHMENT: usize = 16;
+/// Friends whose names a reader agent reads per query.
+const FRIENDS_PER_QUERY: usize = 10;
+/// Players of a gathering that play the speaker role, after the host and before the guests.
+const SPEAKERS: usize = 2;
+
+/// How agents go about their operations.
+#[derive(Copy, Clone, Debug)]
+pub struct OperationOptions {
+    /// Try to batch reads before writes.
+    pub batch_reads: bool,
+    /// Read from a snapshot taken at the start of each operation.
+    pub snapshots: bool,
+    /// Maintain degree counters.
+    pub degrees: bool,
+    /// Give every person an age, and index attribute values for range queries.
+    pub attribute_index: bool,
+    /// Index the writes of each operation, so that its reads see what it has written so far.
+    pub read_own_writes: bool,
+    pub durability: Durability,
+}
+
+/// What particular agents are configured with.
+#[derive(Copy, Clone, Debug)]
+pub struct AgentOptions<'a> {
+    /// How many existing persons a preferential agent befriends each new person with.
+    pub attachments: usize,
+    /// The numbers of players that a gathering agent picks from for each gathering.
+    pub arities: &'a [usize],
+}
+
+/// The names of the popular persons that everyone befriends, repeated to weigh the choice between them.
+pub fn supernodes() -> Vec<Attribute> {
+    #[rustfmt::skip]
+    let names = [
+        0xADE1A1DE,  0xADE1A1DE,  0xADE1A1DE,  0xADE1A1DE,  0xADE1A1DE,
+        0xBAA1,      0xBAA1,      0xBAA1,      0xBAA1,
+        0xB0BB1E,    0xB0BB1E,    0xB0BB1E,
+        0xDEBB1E,    0xDEBB1E,    0xDEBB1E,
+        0x01AF,      0x01AF,
+        0xC0FFEE,    0xC0FFEE,
+        0x0DDBA11,
+        0xB01DFACE,
+    ];
+    names.into_iter().map(|value| Attribute { type_: NAME, value }).collect()
+}
+
+/// Counts of what an agent did, by name.
+pub type AgentStats = Vec<(&'static str, u64)>;
+
+/// A behaviour that agent threads run. Every thread makes its own agent, so agents keep their state and stats per
+/// thread without synchronising.
+pub trait Agent {
+    /// Prepares the thread before its first iteration.
+    fn setup(&mut self, _storage: &Storage) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    /// One operation, which commits at most once.
+    fn iteration(&mut self, storage: &Storage) -> Result<(), StorageError>;
+
+    /// Counts of what the successful iterations of this thread did, by name.
+    fn stats(&self) -> AgentStats;
+}
+
+/// The agents that `--agent` can select.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum AgentKind {
+    /// Registers a person and befriends them with a supernode and some of its friends.
+    Social,
+    /// Registers batches of persons befriended only with each other, without reading anything.
+    Ingest,
+    /// Registers a person and befriends them with existing persons, picked in proportion to their degree counters, so
+    /// that the graph grows like a Barabási–Albert one.
+    Preferential,
+    /// Reads the names and friendship counts of the friends of popular persons, without writing anything.
+    Reader,
+    /// Registers a person and gathers them with random existing persons in one relation of a random arity, as host,
+    /// speakers, and guests, to measure what sibling edges cost as relations grow.
+    Gathering,
+}
 
-pub fn agent(storage: &Storage, stop: &AtomicBool, batch_reads: bool, supernodes: &Vec<Attribute>) {
+impl AgentKind {
+    pub fn make<'a>(
+        self,
+        options: OperationOptions,
+        agent_options: AgentOptions<'a>,
+        supernodes: &'a Vec<Attribute>,
+        arity_report: &'a ArityReport,
+    ) -> Box<dyn Agent + 'a> {
+        match self {
+            Self::Social => Box::new(SocialAgent { options, supernodes, persons: 0, friendships: 0 }),
+            Self::Ingest => Box::new(IngestAgent { options, persons: 0, friendships: 0 }),
+            Self::Preferential => Box::new(PreferentialAgent {
+                options,
+                attachments: agent_options.attachments,
+                persons: 0,
+                friendships: 0,
+                candidates: 0,
+            }),
+            Self::Reader => Box::new(ReaderAgent { options, supernodes, popular: Vec::new(), queries: 0, friends: 0 }),
+            Self::Gathering => Box::new(GatheringAgent {
+                options,
+                arities: agent_options.arities,
+                report: arity_report,
+                gatherings: 0,
+                persons: 0,
+            }),
+        }
+    }
+}
+
+impl FromStr for AgentKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "social" => Ok(Self::Social),
+            "ingest" => Ok(Self::Ingest),
+            "preferential" => Ok(Self::Preferential),
+            "reader" => Ok(Self::Reader),
+            "gathering" => Ok(Self::Gathering),
+            s => Err(format!("Unexpected agent: '{s}'. Expected social, ingest, preferential, reader, or gathering.")),
+        }
+    }
+}
+
+impl Display for AgentKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Social => write!(f, "social"),
+            Self::Ingest => write!(f, "ingest"),
+            Self::Preferential => write!(f, "preferential"),
+            Self::Reader => write!(f, "reader"),
+            Self::Gathering => write!(f, "gathering"),
+        }
+    }
+}
+
+/// Which agents to run on how many threads each, e.g. `social:3,reader:2`. An agent without a count runs on the
+/// default number of threads.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AgentMix(Vec<(AgentKind, Option<usize>)>);
+
+impl AgentMix {
+    /// The agent of every thread, grouped by agent in the order they were given.
+    pub fn threads(&self, default_threads: usize) -> Vec<AgentKind> {
+        self.0.iter().flat_map(|&(kind, threads)| [kind].repeat(threads.unwrap_or(default_threads))).collect()
+    }
+}
+
+impl FromStr for AgentMix {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mix = s
+            .split(',')
+            .map(|entry| match entry.split_once(':') {
+                Some((kind, threads)) => {
+                    let threads =
+                        threads.parse().map_err(|_| format!("Unexpected thread count in agent '{entry}'."))?;
+                    Ok((kind.parse()?, Some(threads)))
+                }
+                None => Ok((entry.parse()?, None)),
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        if !mix.iter().map(|(kind, _)| kind).all_unique() {
+            return Err(format!("Unexpected agents: '{s}'. Every agent may only be given once."));
+        }
+        Ok(Self(mix))
+    }
+}
+
+/// Sets `agent` up, retrying until it succeeds or is stopped. Returns whether it was set up.
+pub fn setup(agent: &mut dyn Agent, storage: &Storage, stop: &AtomicBool, errors: &ErrorCounts) -> bool {
     while !stop.load(Ordering::Relaxed) {
-        let mut writer = storage.writer();
+        match agent.setup(storage) {
+            Ok(()) => return true,
+            Err(err) => errors.record(&err),
+        }
+    }
+    false
+}
 
-        if batch_reads {
-            todo!()
-        } else {
-            let name = Attribute { type_: NAME, value: thread_rng().gen() };
-            let person = register_person(&mut writer, name);
-            make_supernode_friendships(storage, &mut writer, person, supernodes);
-            make_random_friendships(storage, &mut writer, person, supernodes);
+/// Runs iterations of `agent` until stopped. Failed iterations are counted in `errors` rather than `operations`.
+pub fn run(
+    agent: &mut dyn Agent,
+    storage: &Storage,
+    stop: &AtomicBool,
+    operations: &AtomicUsize,
+    errors: &ErrorCounts,
+) {
+    while !stop.load(Ordering::Relaxed) {
+        match agent.iteration(storage) {
+            Ok(()) => {
+                operations.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(err) => errors.record(&err),
         }
+    }
+}
+
+struct SocialAgent<'a> {
+    options: OperationOptions,
+    supernodes: &'a Vec<Attribute>,
+    persons: u64,
+    friendships: u64,
+}
+
+impl Agent for SocialAgent<'_> {
+    fn iteration(&mut self, storage: &Storage) -> Result<(), StorageError> {
+        let friendships = operation(storage, self.options, self.supernodes)?;
+        self.persons += 1;
+        self.friendships += friendships as u64;
+        Ok(())
+    }
 
-        storage.commit(writer);
+    fn stats(&self) -> AgentStats {
+        vec![("persons", self.persons), ("friendships", self.friendships)]
     }
 }
 
-pub fn make_supernode_friendships(
+struct IngestAgent {
+    options: OperationOptions,
+    persons: u64,
+    friendships: u64,
+}
+
+impl Agent for IngestAgent {
+    fn iteration(&mut self, storage: &Storage) -> Result<(), StorageError> {
+        let mut writer = new_writer(storage, self.options);
+        let persons = (0..INGEST_BATCH)
+            .map(|_| register_person(&mut writer, Attribute { type_: NAME, value: thread_rng().gen() }))
+            .collect::<Vec<_>>();
+        for (&person, &friend) in persons.iter().tuple_windows() {
+            befriend(&mut writer, person, friend);
+        }
+        storage.commit(writer)?;
+        self.persons += persons.len() as u64;
+        self.friendships += persons.len().saturating_sub(1) as u64;
+        Ok(())
+    }
+
+    fn stats(&self) -> AgentStats {
+        vec![("persons", self.persons), ("friendships", self.friendships)]
+    }
+}
+
+/// Approximates preferential attachment by sampling: each new person weighs up `CANDIDATES_PER_ATTACHMENT` random
+/// persons per friendship and picks among them in proportion to their degree plus one, so that persons without friends
+/// can still be picked. A person can only win when sampled, which caps the advantage of the largest hubs; more
+/// candidates raise the cap. Without degree counters, every candidate weighs the same.
+struct PreferentialAgent {
+    options: OperationOptions,
+    attachments: usize,
+    persons: u64,
+    friendships: u64,
+    /// Candidates whose degree was read.
+    candidates: u64,
+}
+
+impl Agent for PreferentialAgent {
+    fn iteration(&mut self, storage: &Storage) -> Result<(), StorageError> {
+        let snapshot = new_snapshot(storage, self.options);
+        let mut writer = new_writer(storage, self.options);
+        let person = register_person(&mut writer, Attribute { type_: NAME, value: thread_rng().gen() });
+        let candidates = (0..self.attachments * CANDIDATES_PER_ATTACHMENT)
+            .map(|_| writer.get_random_thing(&snapshot, PERSON))
+            .flatten_ok()
+            .filter_ok(|&candidate| candidate != person)
+            .collect::<Result<HashSet<_>, _>>()?;
+        let weighted = candidates
+            .into_iter()
+            .map(|candidate| {
+                let degree =
+                    if self.options.degrees { writer.degree(&snapshot, candidate, FRIEND, FRIENDSHIP)? } else { 0 };
+                Ok((candidate, degree.max(0) as f64 + 1.0))
+            })
+            .collect::<Result<Vec<_>, StorageError>>()?;
+        let friends = weighted
+            .choose_multiple_weighted(&mut thread_rng(), self.attachments, |&(_, weight)| weight)
+            .expect("degree weights are positive and finite")
+            .map(|&(friend, _)| friend)
+            .collect_vec();
+        for &friend in &friends {
+            befriend(&mut writer, person, friend);
+        }
+        storage.commit(writer)?;
+        self.persons += 1;
+        self.friendships += friends.len() as u64;
+        if self.options.degrees {
+            self.candidates += weighted.len() as u64;
+        }
+        Ok(())
+    }
+
+    fn stats(&self) -> AgentStats {
+        vec![("persons", self.persons), ("friendships", self.friendships), ("candidates weighed", self.candidates)]
+    }
+}
+
+struct ReaderAgent<'a> {
+    options: OperationOptions,
+    supernodes: &'a Vec<Attribute>,
+    /// The persons named by the supernodes, weighted like them.
+    popular: Vec<Thing>,
+    queries: u64,
+    friends: u64,
+}
+
+impl Agent for ReaderAgent<'_> {
+    fn setup(&mut self, storage: &Storage) -> Result<(), StorageError> {
+        let popular = self.supernodes.iter().map(|name| storage.get_one_owner(&ReadSnapshot::Latest, name));
+        self.popular = popular.flatten_ok().collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    /// Reads up to `FRIENDS_PER_QUERY` friends of a random friend of a popular person, with their names and, if
+    /// maintained, friendship counts.
+    fn iteration(&mut self, storage: &Storage) -> Result<(), StorageError> {
+        let snapshot = new_snapshot(storage, self.options);
+        let Some(&popular) = self.popular.choose(&mut thread_rng()) else { return Ok(()) };
+        let person = storage.get_random_sibling(&snapshot, popular, FRIEND, FRIENDSHIP)?.unwrap_or(popular);
+        let mut friends = 0;
+        for friend in storage.iter_siblings(&snapshot, person, FRIEND, FRIENDSHIP).take(FRIENDS_PER_QUERY) {
+            let friend = friend?;
+            storage.get_one_has(&snapshot, friend)?;
+            if self.options.degrees {
+                storage.degree(&snapshot, friend, FRIEND, FRIENDSHIP)?;
+            }
+            friends += 1;
+        }
+        self.queries += 1;
+        self.friends += friends;
+        Ok(())
+    }
+
+    fn stats(&self) -> AgentStats {
+        vec![("queries", self.queries), ("friends read", self.friends)]
+    }
+}
+
+struct GatheringAgent<'a> {
+    options: OperationOptions,
+    arities: &'a [usize],
+    report: &'a ArityReport,
+    gatherings: u64,
+    /// Persons registered, one per gathering plus any needed to make up the numbers in a small graph.
+    persons: u64,
+}
+
+impl Agent for GatheringAgent<'_> {
+    fn iteration(&mut self, storage: &Storage) -> Result<(), StorageError> {
+        let snapshot = new_snapshot(storage, self.options);
+        let mut writer = new_writer(storage, self.options);
+        let arity = *self.arities.choose(&mut thread_rng()).unwrap();
+        let host = register_person(&mut writer, Attribute { type_: NAME, value: thread_rng().gen() });
+        let mut players = vec![host];
+        let existing = (1..arity)
+            .map(|_| writer.get_random_thing(&snapshot, PERSON))
+            .flatten_ok()
+            .filter_ok(|&person| person != host)
+            .collect::<Result<HashSet<_>, _>>()?;
+        players.extend(existing);
+        let mut persons = 1;
+        while players.len() < arity {
+            players.push(register_person(&mut writer, Attribute { type_: NAME, value: thread_rng().gen() }));
+            persons += 1;
+        }
+
+        let roles = [HOST].into_iter().chain([SPEAKER; SPEAKERS]).chain(std::iter::repeat(GUEST));
+        let rel = Thing { type_: GATHERING, thing_id: ThingID { id: thread_rng().gen() } };
+        writer.put_relation(rel, roles.zip(players));
+        let written = KeySpace::ALL.map(|key_space| writer.written(key_space));
+        let start = Instant::now();
+        storage.commit(writer)?;
+        self.report.record(arity, &written, start.elapsed());
+        self.gatherings += 1;
+        self.persons += persons;
+        Ok(())
+    }
+
+    fn stats(&self) -> AgentStats {
+        vec![("gatherings", self.gatherings), ("persons", self.persons)]
+    }
+}
+
+/// What the commits of gathering agents wrote and how long they took, by the arity of their gathering. Shared by the
+/// threads of all gathering agents.
+#[derive(Debug, Default)]
+pub struct ArityReport {
+    by_arity: Mutex<BTreeMap<usize, ArityStats>>,
+}
+
+#[derive(Debug, Default)]
+struct ArityStats {
+    commits: u64,
+    /// Keys and bytes written by the commits, by key space.
+    written: [(u64, u64); KeySpace::ALL.len()],
+    latencies: LatencyHistogram,
+}
+
+impl ArityReport {
+    fn record(&self, arity: usize, written: &[(u64, u64); KeySpace::ALL.len()], latency: Duration) {
+        let mut by_arity = self.by_arity.lock().unwrap();
+        let stats = by_arity.entry(arity).or_default();
+        stats.commits += 1;
+        for (total, (keys, bytes)) in stats.written.iter_mut().zip(written) {
+            total.0 += keys;
+            total.1 += bytes;
+        }
+        stats.latencies.record(latency);
+    }
+
+    /// Prints, per arity, what one gathering commit wrote on average and how long it took, over the whole run. Prints
+    /// nothing if no gathering was committed. The latencies are taken, so a second print has none.
+    pub fn print(&self) {
+        let by_arity = self.by_arity.lock().unwrap();
+        if by_arity.is_empty() {
+            return;
+        }
+        println!("Gatherings by arity (per commit, which includes any persons registered for it):");
+        println!(
+            "  {:>5} {:>8} {:>14} {:>14} {:>14} {:>12} {:>12} {:>12}",
+            "arity",
+            "commits",
+            "sibling edges",
+            "sibling bytes",
+            "relates edges",
+            "bytes put",
+            "commit p50",
+            "commit p99"
+        );
+        for (arity, stats) in by_arity.iter() {
+            let per_commit = |key_spaces: &[KeySpace], bytes: bool| {
+                let total = key_spaces
+                    .iter()
+                    .map(|&key_space| stats.written[key_space as usize])
+                    .map(|(keys, key_bytes)| if bytes { key_bytes } else { keys })
+                    .sum::<u64>();
+                total as f64 / stats.commits as f64
+            };
+            let latencies = stats.latencies.take();
+            let percentile = |quantile| latencies.percentile(quantile).map_or("-".to_owned(), |p| format!("{p:.2?}"));
+            println!(
+                "  {arity:>5} {:>8} {:>14.1} {:>14} {:>14.1} {:>12} {:>12} {:>12}",
+                stats.commits,
+                per_commit(&[KeySpace::RelationSibling], false),
+                Bytes(per_commit(&[KeySpace::RelationSibling], true) as u64).to_string(),
+                per_commit(&[KeySpace::RelatesForward, KeySpace::RelatesBackward], false),
+                Bytes(per_commit(&KeySpace::ALL, true) as u64).to_string(),
+                percentile(0.5),
+                percentile(0.99),
+            );
+        }
+    }
+}
+
+fn new_writer(storage: &Storage, options: OperationOptions) -> WriteHandle<'_> {
+    let mut writer = storage.writer().with_durability(options.durability);
+    if !options.degrees {
+        writer = writer.without_degrees();
+    }
+    if options.attribute_index {
+        writer = writer.with_attribute_index();
+    }
+    if options.read_own_writes {
+        writer = writer.indexed();
+    }
+    writer
+}
+
+fn new_snapshot(storage: &Storage, options: OperationOptions) -> ReadSnapshot<'_> {
+    if options.snapshots {
+        storage.snapshot()
+    } else {
+        ReadSnapshot::Latest
+    }
+}
+
+/// Registers a person and befriends them with a supernode and some of its friends, in one commit. Returns how many
+/// friendships it made.
+pub fn operation(
+    storage: &Storage,
+    options: OperationOptions,
+    supernodes: &Vec<Attribute>,
+) -> Result<usize, StorageError> {
+    let mut writer = new_writer(storage, options);
+    let friendships = write_operation(storage, &mut writer, options, supernodes)?;
+    storage.commit(writer)?;
+    Ok(friendships)
+}
+
+/// The writes of one `operation`, left uncommitted in `writer`. Returns how many friendships it made.
+pub fn write_operation(
     storage: &Storage,
     writer: &mut WriteHandle,
-    person: Thing,
+    options: OperationOptions,
     supernodes: &Vec<Attribute>,
+) -> Result<usize, StorageError> {
+    let snapshot = new_snapshot(storage, options);
+    if options.batch_reads {
+        todo!()
+    } else {
+        let name = Attribute { type_: NAME, value: thread_rng().gen() };
+        let person = register_person(writer, name);
+        Ok(make_supernode_friendships(&snapshot, writer, person, supernodes)?
+            + make_random_friendships(&snapshot, writer, person, supernodes)?)
+    }
+}
+
+/// Repeatedly counts the persons whose age falls in a random range, until stopped.
+pub fn range_reader(
+    storage: &Storage,
+    stop: &AtomicBool,
+    queries: &AtomicUsize,
+    errors: &ErrorCounts,
+    snapshots: bool,
 ) {
+    while !stop.load(Ordering::Relaxed) {
+        let snapshot = if snapshots { storage.snapshot() } else { ReadSnapshot::Latest };
+        let lo = thread_rng().gen_range(0..100 - AGE_RANGE_WIDTH);
+        let query =
+            storage.owners_in_range(&snapshot, AGE, lo..lo + AGE_RANGE_WIDTH).try_for_each(|owner| owner.map(drop));
+        match query {
+            Ok(()) => {
+                queries.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(err) => errors.record(&err),
+        }
+    }
+}
+
+pub fn make_supernode_friendships(
+    snapshot: &ReadSnapshot,
+    writer: &mut WriteHandle,
+    person: Thing,
+    supernodes: &Vec<Attribute>,
+) -> Result<usize, StorageError> {
     let name = supernodes.choose(&mut thread_rng()).unwrap();
-    if let Some(popular) = storage.get_one_owner(name) {
-        let rel = Thing { type_: FRIENDSHIP, thing_id: ThingID { id: thread_rng().gen() } };
-        writer.put_relation(rel, [(FRIEND, popular), (FRIEND, person)]);
+    match writer.get_one_owner(snapshot, name)? {
+        Some(popular) => {
+            befriend(writer, popular, person);
+            Ok(1)
+        }
+        None => Ok(0),
     }
 }
 
 pub fn make_random_friendships(
-    storage: &Storage,
+    snapshot: &ReadSnapshot,
     writer: &mut WriteHandle,
     person: Thing,
     supernodes: &Vec<Attribute>,
-) {
+) -> Result<usize, StorageError> {
+    let mut friendships = 0;
     for _ in 0..5 {
         let name = supernodes.choose(&mut thread_rng()).unwrap();
-        if let Some(popular) = storage.get_one_owner(name) {
-            if let Some(rando) = storage.get_random_sibling(popular, FRIEND, FRIENDSHIP) {
-                let rel = Thing { type_: FRIENDSHIP, thing_id: ThingID { id: thread_rng().gen() } };
-                writer.put_relation(rel, [(FRIEND, rando), (FRIEND, person)]);
+        if let Some(popular) = writer.get_one_owner(snapshot, name)? {
+            if let Some(rando) = writer.get_random_sibling(snapshot, popular, FRIEND, FRIENDSHIP)? {
+                befriend(writer, rando, person);
+                friendships += 1;
             }
         }
     }
+    Ok(friendships)
+}
+
+pub fn befriend(writer: &mut WriteHandle, person: Thing, friend: Thing) {
+    let rel = Thing { type_: FRIENDSHIP, thing_id: ThingID { id: thread_rng().gen() } };
+    writer.put_relation(rel, [(FRIEND, person), (FRIEND, friend)]);
 }
 
 pub fn register_person(writer: &mut WriteHandle, name: Attribute) -> Thing {
@@ -66,5 +623,11 @@ pub fn register_person(writer: &mut WriteHandle, name: Attribute) -> Thing {
     let person = Thing { type_: PERSON, thing_id: ThingID { id: thread_rng().gen() } };
     writer.put_entity(person);
     writer.put_ownership(person, name);
+    // only range queries read ages, and they need the attribute index
+    if writer.attribute_index() {
+        let age = Attribute { type_: AGE, value: thread_rng().gen_range(0..100) };
+        writer.put_attribute(age);
+        writer.put_ownership(person, age);
+    }
     person
 }
@@ -7,6 +7,7 @@ pub enum Prefix {
     Entity = 0x22,
     Relation = 0x33,
     Attribute = 0x44,
+    AttributeIndex = 0x4A,
 }
 
 #[repr(C, packed)]
@@ -60,6 +61,57 @@ impl Attribute {
     pub fn as_bytes(&self) -> &[u8; size_of::<Self>()] {
         unsafe { transmute(self) }
     }
+
+    pub fn from_bytes(bytes: [u8; size_of::<Self>()]) -> Self {
+        unsafe { transmute(bytes) }
+    }
+}
+
+/// An entry in the value-ordered index of an attribute type. Unlike `Attribute`, the encoding leads with the value in
+/// big-endian order, so that entries sort by value within each attribute type.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AttributeIndexEntry {
+    pub attr: Attribute,
+    pub owner: Thing,
+}
+
+impl AttributeIndexEntry {
+    pub fn to_bytes(self) -> [u8; size_of::<AttributeIndexEntryEncoded>()] {
+        let Self { attr: Attribute { type_, value }, owner } = self;
+        AttributeIndexEntryEncoded { type_: Self::index_type(type_), value: value.to_be_bytes(), owner }.to_bytes()
+    }
+
+    pub const fn encoding_size() -> usize {
+        size_of::<AttributeIndexEntryEncoded>()
+    }
+
+    pub fn from_bytes(bytes: [u8; size_of::<AttributeIndexEntryEncoded>()]) -> Self {
+        let AttributeIndexEntryEncoded { type_, value, owner } = AttributeIndexEntryEncoded::from_bytes(bytes);
+        let type_ = AttributeType { prefix: Prefix::Attribute, ..type_ };
+        Self { attr: Attribute { type_, value: u64::from_be_bytes(value) }, owner }
+    }
+
+    /// The bytes that all index entries of `attr_type` start with.
+    pub fn type_prefix(attr_type: AttributeType) -> [u8; size_of::<AttributeType>()] {
+        Self::index_type(attr_type).to_bytes()
+    }
+
+    /// The bytes that index entries of `attr_type` with `value` start with.
+    pub fn value_prefix(attr_type: AttributeType, value: u64) -> Vec<u8> {
+        [&Self::type_prefix(attr_type) as &[u8], &value.to_be_bytes()].concat()
+    }
+
+    fn index_type(attr_type: AttributeType) -> AttributeType {
+        AttributeType { prefix: Prefix::AttributeIndex, ..attr_type }
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct AttributeIndexEntryEncoded {
+    type_: AttributeType,
+    value: [u8; size_of::<u64>()],
+    owner: Thing,
 }
 
 #[repr(u8)]
@@ -67,6 +119,7 @@ impl Attribute {
 pub enum EdgeType {
     Has = 0x55,
     Sibling = 0x5A,
+    Degree = 0x66,
     Relates = 0xAA,
 }
 
@@ -82,6 +135,7 @@ impl HasEdge {
         HasForwardEdge { owner, attr, edge_type: EdgeType::Has }.to_bytes()
     }
 
+    #[allow(dead_code)]
     pub const fn forward_encoding_size() -> usize {
         size_of::<HasForwardEdge>()
     }
@@ -139,6 +193,21 @@ impl RelatesEdge {
         let Self { rel, role_type, player } = self;
         RelatesBackwardEdge { rel, role_type, player, edge_type: EdgeType::Relates }.to_bytes()
     }
+
+    #[allow(dead_code)]
+    pub const fn forward_encoding_size() -> usize {
+        size_of::<RelatesForwardEdge>()
+    }
+
+    pub fn from_bytes_forward(bytes: [u8; size_of::<RelatesForwardEdge>()]) -> Self {
+        let RelatesForwardEdge { rel, edge_type: _, role_type, player } = RelatesForwardEdge::from_bytes(bytes);
+        Self { rel, role_type, player }
+    }
+
+    pub fn from_bytes_backward(bytes: [u8; size_of::<RelatesBackwardEdge>()]) -> Self {
+        let RelatesBackwardEdge { player, edge_type: _, role_type, rel } = RelatesBackwardEdge::from_bytes(bytes);
+        Self { rel, role_type, player }
+    }
 }
 
 #[repr(C, packed)]
@@ -194,6 +263,7 @@ impl RelationSiblingEdge {
         .to_forward_bytes()
     }
 
+    #[allow(dead_code)]
     pub const fn encoding_size() -> usize {
         size_of::<RelationSiblingEdgeEncoded>()
     }
@@ -205,6 +275,35 @@ impl RelationSiblingEdge {
     }
 }
 
+/// The key of the counter of how many relations of `rel_type` a player plays `role_type` in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DegreeKey {
+    pub player: Thing,
+    pub role_type: Type,
+    pub rel_type: Type,
+}
+
+impl DegreeKey {
+    pub fn to_bytes(self) -> [u8; size_of::<DegreeKeyEncoded>()] {
+        let Self { player, role_type, rel_type } = self;
+        DegreeKeyEncoded { player, edge_type: EdgeType::Degree, role_type, rel_type }.to_bytes()
+    }
+
+    pub fn from_bytes(bytes: [u8; size_of::<DegreeKeyEncoded>()]) -> Self {
+        let DegreeKeyEncoded { player, edge_type: _, role_type, rel_type } = DegreeKeyEncoded::from_bytes(bytes);
+        Self { player, role_type, rel_type }
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct DegreeKeyEncoded {
+    player: Thing,
+    edge_type: EdgeType,
+    role_type: Type,
+    rel_type: Type,
+}
+
 #[repr(C, packed)]
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 struct RelationSiblingEdgeEncoded {
@@ -226,6 +325,9 @@ macro_rules! bytes {
             pub fn to_bytes(self) -> [u8; size_of::<Self>()] {
                 unsafe { transmute(self) }
             }
+            pub fn from_bytes(bytes: [u8; size_of::<Self>()]) -> Self {
+                unsafe { transmute(bytes) }
+            }
         }
     )*};
 }
@@ -233,6 +335,9 @@ macro_rules! bytes {
 bytes! {
     Thing
     Type
+    AttributeType
+
+    AttributeIndexEntryEncoded
 
     HasBackwardEdge
     HasForwardEdge
@@ -241,4 +346,6 @@ bytes! {
     RelatesBackwardEdge
 
     RelationSiblingEdgeEncoded
+
+    DegreeKeyEncoded
 }
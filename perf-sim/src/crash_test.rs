@@ -0,0 +1,246 @@
+use std::{
+    collections::HashMap,
+    env,
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    process::{Command, Stdio},
+    sync::mpsc::{self, Sender},
+    thread,
+    time::Duration,
+};
+
+use itertools::Itertools;
+use rand::{thread_rng, Rng};
+
+use crate::{
+    agent::{self, OperationOptions},
+    concept::{DegreeKey, RelatesEdge},
+    storage::{Durability, KeySpace, ReadSnapshot, Reads, Storage, StorageError},
+    Mode,
+};
+
+/// How long the writer child gets to open its store and acknowledge its first commit.
+const START_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// One commit as announced by the writer child, with every key it put.
+#[derive(Debug, Default)]
+pub(crate) struct Commit {
+    pub(crate) keys: Vec<(KeySpace, Vec<u8>)>,
+    /// Whether the child was about to commit, having announced all of its keys.
+    pub(crate) submitted: bool,
+    /// Whether the commit returned before the child was killed.
+    pub(crate) acknowledged: bool,
+}
+
+/// What became of the commits of one killed child.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Outcome {
+    pub acknowledged: usize,
+    /// Commits that are fully present, acknowledged or not.
+    pub present: usize,
+    /// Acknowledged commits that are entirely missing.
+    pub lost: usize,
+    /// Commits that are partly present.
+    pub torn: usize,
+    /// Degree counters that don't match the relates edges present.
+    pub miscounted: usize,
+}
+
+impl Outcome {
+    /// Torn commits and miscounted degrees are never allowed. Lost commits only are when commits skip the write-ahead
+    /// log.
+    pub fn is_consistent(&self, durability: Durability) -> bool {
+        self.torn == 0 && self.miscounted == 0 && (durability == Durability::None || self.lost == 0)
+    }
+}
+
+/// Crashes a writer `iterations` times for every mode and durability, printing what became of the commits. Returns
+/// whether every outcome was consistent.
+pub fn run(
+    storage_dir: &Path,
+    modes: &[Mode],
+    durabilities: &[Durability],
+    iterations: usize,
+    kill_within: Duration,
+) -> io::Result<bool> {
+    let mut consistent = true;
+    for (&mode, &durability) in modes.iter().cartesian_product(durabilities) {
+        let mut total = Outcome::default();
+        for iteration in 0..iterations {
+            let outcome = crash_once(storage_dir, mode, durability, kill_within)?;
+            let Outcome { acknowledged, present, lost, torn, miscounted } = outcome;
+            println!(
+                "{mode} / {durability} #{iteration}: {acknowledged} acknowledged, {present} present, {lost} lost, \
+                 {torn} torn, {miscounted} degrees miscounted"
+            );
+            total.acknowledged += acknowledged;
+            total.present += present;
+            total.lost += lost;
+            total.torn += torn;
+            total.miscounted += miscounted;
+        }
+        let verdict = if total.is_consistent(durability) { "consistent" } else { "INCONSISTENT" };
+        println!(
+            "{mode} / {durability}: {verdict} ({} acknowledged, {} lost, {} torn, {} degrees miscounted over \
+             {iterations} crashes)",
+            total.acknowledged, total.lost, total.torn, total.miscounted
+        );
+        consistent &= total.is_consistent(durability);
+    }
+    Ok(consistent)
+}
+
+/// Runs a writer child against a fresh store, kills it with SIGKILL at a random point within `kill_within` of its first
+/// acknowledged commit, then reopens the store and checks every commit the child announced. Fails if the child never
+/// acknowledges a commit or exits before it is killed, as there would be nothing to check.
+pub fn crash_once(
+    storage_dir: &Path,
+    mode: Mode,
+    durability: Durability,
+    kill_within: Duration,
+) -> io::Result<Outcome> {
+    let mut child = Command::new(env::current_exe()?)
+        .args(["--mode", &mode.to_string(), "--dir"])
+        .arg(storage_dir)
+        .args(["--durability", &durability.to_string(), "crash-writer"])
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let stdout = BufReader::new(child.stdout.take().unwrap());
+    let (started, first_acknowledged) = mpsc::channel();
+    let reader = thread::spawn(move || read_commits(stdout, started));
+
+    if let Err(error) = first_acknowledged.recv_timeout(START_TIMEOUT) {
+        child.kill()?;
+        let status = child.wait()?;
+        return Err(io::Error::other(format!("writer acknowledged no commit ({error}), exited with {status}")));
+    }
+    thread::sleep(kill_within.mul_f64(thread_rng().gen()));
+    if let Some(status) = child.try_wait()? {
+        return Err(io::Error::other(format!("writer exited with {status} before it was killed")));
+    }
+    child.kill()?;
+    child.wait()?;
+    let commits = reader.join().unwrap()?;
+
+    let storage = Storage::open(storage_dir, mode).map_err(io::Error::other)?;
+    check(&storage, &commits).map_err(io::Error::other)
+}
+
+/// The child's side: commits agent operations into a fresh store until killed, announcing each on stdout before it
+/// commits and acknowledging it once the commit has returned.
+pub fn crash_writer(storage_dir: &Path, mode: Mode, durability: Durability) -> io::Result<()> {
+    let storage = Storage::new(storage_dir, mode).map_err(io::Error::other)?;
+    let options = OperationOptions {
+        batch_reads: false,
+        snapshots: false,
+        degrees: true,
+        attribute_index: false,
+        read_own_writes: false,
+        durability,
+    };
+    let supernodes = agent::supernodes();
+    let mut out = io::stdout().lock();
+
+    let mut writer = storage.writer().with_durability(durability).recording();
+    for name in supernodes.iter().unique() {
+        agent::register_person(&mut writer, *name);
+    }
+    loop {
+        writeln!(out, "begin")?;
+        for (key_space, key) in writer.recorded().unwrap() {
+            let index = KeySpace::ALL.iter().position(|ks| ks == key_space).unwrap();
+            writeln!(out, "put {index} {}", key.iter().map(|byte| format!("{byte:02x}")).collect::<String>())?;
+        }
+        writeln!(out, "commit")?;
+        out.flush()?;
+        storage.commit(writer).map_err(io::Error::other)?;
+        writeln!(out, "done")?;
+        out.flush()?;
+
+        writer = storage.writer().with_durability(durability).recording();
+        agent::write_operation(&storage, &mut writer, options, &supernodes).map_err(io::Error::other)?;
+    }
+}
+
+/// Collects the commits announced on `input`, sending on `started` once the first is acknowledged.
+fn read_commits(input: impl BufRead, started: Sender<()>) -> io::Result<Vec<Commit>> {
+    let mut started = Some(started);
+    let mut commits = Vec::<Commit>::new();
+    for line in input.lines() {
+        let line = line?;
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("unexpected line from writer: '{line}'"));
+        match line.split_whitespace().collect::<Vec<_>>()[..] {
+            ["begin"] => commits.push(Commit::default()),
+            ["put", index, key] => {
+                let key_space =
+                    index.parse::<usize>().ok().and_then(|index| KeySpace::ALL.get(index)).ok_or_else(invalid)?;
+                let key = (0..key.len())
+                    .step_by(2)
+                    .map(|i| key.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(invalid)?;
+                commits.last_mut().ok_or_else(invalid)?.keys.push((*key_space, key));
+            }
+            ["commit"] => commits.last_mut().ok_or_else(invalid)?.submitted = true,
+            ["done"] => {
+                commits.last_mut().ok_or_else(invalid)?.acknowledged = true;
+                if let Some(started) = started.take() {
+                    // the receiver is gone if it gave up waiting
+                    started.send(()).ok();
+                }
+            }
+            // the child may be killed halfway through a line
+            _ => break,
+        }
+    }
+    Ok(commits)
+}
+
+/// Checks each commit by the keys that only it put: keys that several commits put (like an age attribute) could be
+/// present because of any of them. Then checks the degree counters against the relates edges present, as a commit
+/// merges into the counters of the edges it puts or deletes.
+pub(crate) fn check(storage: &Storage, commits: &[Commit]) -> Result<Outcome, StorageError> {
+    let commits = commits.iter().filter(|commit| commit.submitted).collect::<Vec<_>>();
+    let mut put_by = HashMap::<&(KeySpace, Vec<u8>), usize>::new();
+    for key in commits.iter().flat_map(|commit| &commit.keys) {
+        *put_by.entry(key).or_default() += 1;
+    }
+
+    let mut outcome = Outcome::default();
+    for commit in commits {
+        let own_keys = commit.keys.iter().filter(|key| put_by[key] == 1).collect::<Vec<_>>();
+        let present = own_keys
+            .iter()
+            .map(|(key_space, key)| storage.contains(*key_space, key).map(usize::from))
+            .sum::<Result<usize, _>>()?;
+        outcome.acknowledged += commit.acknowledged as usize;
+        match present {
+            0 if commit.acknowledged => outcome.lost += 1,
+            0 => (),
+            present if present == own_keys.len() => outcome.present += 1,
+            _ => outcome.torn += 1,
+        }
+    }
+    outcome.miscounted = miscounted_degrees(storage)?;
+    Ok(outcome)
+}
+
+/// Counts the degree counters that differ from the number of relates edges they count, including edges with no
+/// counter at all.
+fn miscounted_degrees(storage: &Storage) -> Result<usize, StorageError> {
+    let snapshot = ReadSnapshot::Latest;
+    let mut edges = HashMap::<DegreeKey, i64>::new();
+    for edge in storage.iter_relates(&snapshot) {
+        let RelatesEdge { rel, role_type, player } = edge?;
+        *edges.entry(DegreeKey { player, role_type, rel_type: rel.type_ }).or_default() += 1;
+    }
+    for key in storage.iter_degree_keys(&snapshot) {
+        edges.entry(key?).or_default();
+    }
+    edges
+        .into_iter()
+        .map(|(DegreeKey { player, role_type, rel_type }, count)| {
+            Ok(usize::from(storage.degree(&snapshot, player, role_type, rel_type)? != count))
+        })
+        .sum()
+}
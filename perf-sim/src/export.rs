@@ -0,0 +1,125 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    str::FromStr,
+};
+
+use itertools::Itertools;
+
+use crate::{
+    agent::{FRIENDSHIP, NAME, PERSON},
+    concept::{HasEdge, RelatesEdge, Thing},
+    storage::Storage,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    GraphML,
+    Dot,
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "graphml" => Ok(Self::GraphML),
+            "dot" => Ok(Self::Dot),
+            "csv" => Ok(Self::Csv),
+            s => Err(format!("Unexpected export format: '{s}'. Expected graphml, dot, or csv.")),
+        }
+    }
+}
+
+/// Writes every person in a snapshot of the storage as a node labelled with its name, and every friendship as an edge
+/// between its players. The CSV format is a plain edge list, so persons without friendships don't appear in it. Storage
+/// errors come back as `io::Error`s wrapping the `StorageError`.
+pub fn export(storage: &Storage, format: Format, out: &mut impl Write) -> io::Result<()> {
+    let snapshot = storage.snapshot();
+    let names: HashMap<Thing, u64> = storage
+        .iter_has(&snapshot)
+        .filter_ok(|HasEdge { owner, attr }| owner.type_ == PERSON && attr.type_ == NAME)
+        .map_ok(|HasEdge { owner, attr }| (owner, attr.value))
+        .collect::<Result<_, _>>()
+        .map_err(io::Error::other)?;
+
+    write_prologue(format, out)?;
+
+    if format != Format::Csv {
+        for person in storage.iter_things(&snapshot).filter_ok(|thing| thing.type_ == PERSON) {
+            let person = person.map_err(io::Error::other)?;
+            write_node(format, out, person, names.get(&person).copied())?;
+        }
+    }
+
+    let written = storage.iter_relates(&snapshot).process_results(|edges| {
+        let friendships = edges.filter(|edge| edge.rel.type_ == FRIENDSHIP).group_by(|edge| edge.rel);
+        for (rel, edges) in &friendships {
+            let players = edges.map(|RelatesEdge { player, .. }| player).collect_vec();
+            for (lhs, rhs) in players.into_iter().tuple_combinations() {
+                write_edge(format, out, rel, (lhs, names.get(&lhs).copied()), (rhs, names.get(&rhs).copied()))?;
+            }
+        }
+        io::Result::Ok(())
+    });
+    written.map_err(io::Error::other)??;
+
+    write_epilogue(format, out)
+}
+
+fn write_prologue(format: Format, out: &mut impl Write) -> io::Result<()> {
+    match format {
+        Format::GraphML => {
+            writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+            writeln!(out, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#)?;
+            // names are u64s, which GraphML's signed long can't hold
+            writeln!(out, r#"  <key id="name" for="node" attr.name="name" attr.type="string"/>"#)?;
+            writeln!(out, r#"  <graph id="perf-sim" edgedefault="undirected">"#)
+        }
+        Format::Dot => writeln!(out, "graph perf_sim {{"),
+        Format::Csv => writeln!(out, "source,target,relation,source_name,target_name"),
+    }
+}
+
+fn write_node(format: Format, out: &mut impl Write, person: Thing, name: Option<u64>) -> io::Result<()> {
+    let id = person.thing_id.id;
+    match (format, name) {
+        (Format::GraphML, Some(name)) => {
+            writeln!(out, r#"    <node id="{id}"><data key="name">{name}</data></node>"#)
+        }
+        (Format::GraphML, None) => writeln!(out, r#"    <node id="{id}"/>"#),
+        (Format::Dot, Some(name)) => writeln!(out, r#"  {id} [label="{name}"];"#),
+        (Format::Dot, None) => writeln!(out, "  {id};"),
+        (Format::Csv, _) => Ok(()),
+    }
+}
+
+fn write_edge(
+    format: Format,
+    out: &mut impl Write,
+    rel: Thing,
+    (lhs, lhs_name): (Thing, Option<u64>),
+    (rhs, rhs_name): (Thing, Option<u64>),
+) -> io::Result<()> {
+    let (rel, lhs, rhs) = (rel.thing_id.id, lhs.thing_id.id, rhs.thing_id.id);
+    match format {
+        Format::GraphML => writeln!(out, r#"    <edge id="{rel}-{lhs}-{rhs}" source="{lhs}" target="{rhs}"/>"#),
+        Format::Dot => writeln!(out, "  {lhs} -- {rhs};"),
+        Format::Csv => {
+            let lhs_name = lhs_name.map(|name| name.to_string()).unwrap_or_default();
+            let rhs_name = rhs_name.map(|name| name.to_string()).unwrap_or_default();
+            writeln!(out, "{lhs},{rhs},{rel},{lhs_name},{rhs_name}")
+        }
+    }
+}
+
+fn write_epilogue(format: Format, out: &mut impl Write) -> io::Result<()> {
+    match format {
+        Format::GraphML => {
+            writeln!(out, "  </graph>")?;
+            writeln!(out, "</graphml>")
+        }
+        Format::Dot => writeln!(out, "}}"),
+        Format::Csv => Ok(()),
+    }
+}
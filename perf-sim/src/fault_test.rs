@@ -0,0 +1,82 @@
+use std::path::Path;
+
+use bench_utils::faults::Faults;
+use itertools::Itertools;
+
+use crate::{
+    agent::{self, OperationOptions},
+    crash_test::{self, Commit, Outcome},
+    storage::{Durability, Storage, StorageError},
+    Mode,
+};
+
+/// Runs `operations` agent operations against a fresh store for every mode, injecting `faults` into them, then checks
+/// that every commit either landed whole or not at all, degree counters included. Returns whether that held in every
+/// mode.
+pub fn run(
+    storage_dir: &Path,
+    modes: &[Mode],
+    durability: Durability,
+    faults: &Faults,
+    operations: usize,
+) -> Result<bool, StorageError> {
+    let mut consistent = true;
+    for &mode in modes {
+        let (outcome, failed, aborted, injected) = run_mode(storage_dir, mode, durability, faults.clone(), operations)?;
+        let Outcome { acknowledged, present, lost, torn, miscounted } = outcome;
+        let verdict = if torn == 0 && lost == 0 && miscounted == 0 { "consistent" } else { "INCONSISTENT" };
+        println!(
+            "{mode} / {durability}: {verdict} ({acknowledged} commits acknowledged, {failed} failed, {present} present, \
+             {lost} lost, {torn} torn, {miscounted} degrees miscounted; {aborted} operations failed before \
+             committing; injected {injected})"
+        );
+        consistent &= torn == 0 && lost == 0 && miscounted == 0;
+    }
+    Ok(consistent)
+}
+
+/// Returns the outcome of the commits, how many of them failed, how many operations failed before getting to commit,
+/// and a summary of the injected faults.
+fn run_mode(
+    storage_dir: &Path,
+    mode: Mode,
+    durability: Durability,
+    faults: Faults,
+    operations: usize,
+) -> Result<(Outcome, usize, usize, String), StorageError> {
+    let storage = Storage::new(storage_dir, mode)?;
+    let options = OperationOptions {
+        batch_reads: false,
+        snapshots: false,
+        degrees: true,
+        attribute_index: false,
+        read_own_writes: false,
+        durability,
+    };
+    let supernodes = agent::supernodes();
+    let mut writer = storage.writer().with_durability(durability);
+    for name in supernodes.iter().unique() {
+        agent::register_person(&mut writer, *name);
+    }
+    storage.commit(writer)?;
+
+    let storage = storage.with_faults(faults);
+    let mut commits = Vec::new();
+    let mut aborted = 0;
+    for _ in 0..operations {
+        let mut writer = storage.writer().with_durability(durability).recording();
+        if agent::write_operation(&storage, &mut writer, options, &supernodes).is_err() {
+            aborted += 1;
+            continue;
+        }
+        let keys = writer.recorded().unwrap().to_vec();
+        let acknowledged = storage.commit(writer).is_ok();
+        commits.push(Commit { keys, submitted: true, acknowledged });
+    }
+    let failed = commits.iter().filter(|commit| !commit.acknowledged).count();
+    let injected = storage.faults().summary();
+
+    // check without faults, so that the reads are reliable
+    let storage = storage.with_faults(Faults::default());
+    Ok((crash_test::check(&storage, &commits)?, failed, aborted, injected))
+}
@@ -0,0 +1,121 @@
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    io::{self, BufRead},
+    str::FromStr,
+};
+
+use rand::{thread_rng, Rng};
+
+use crate::{
+    agent::{FRIEND, FRIENDSHIP, NAME, PERSON},
+    concept::{Attribute, Thing, ThingID},
+    storage::{Durability, Storage, WriteHandle},
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Snap,
+}
+
+impl FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "snap" => Ok(Self::Snap),
+            s => Err(format!("Unexpected import format: '{s}'. Expected csv or snap.")),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Summary {
+    pub persons: usize,
+    pub friendships: usize,
+    pub skipped: usize,
+}
+
+/// Loads an undirected edge list of numeric external IDs as persons and friendships.
+///
+/// Each external ID becomes a person named after it. Self-loops and repeated pairs (in either direction) are skipped.
+/// Writes are committed every `batch_size` friendships. Storage errors come back as `io::Error`s wrapping the
+/// `StorageError`.
+pub fn import(
+    storage: &Storage,
+    format: Format,
+    input: impl BufRead,
+    batch_size: usize,
+    durability: Durability,
+) -> io::Result<Summary> {
+    let mut persons = HashMap::<u64, Thing>::new();
+    let mut seen = HashSet::<(u64, u64)>::new();
+    let mut summary = Summary::default();
+
+    let mut writer = storage.writer().with_durability(durability);
+    let mut pending = 0;
+    for (line_number, line) in input.lines().enumerate() {
+        let line = line?;
+        let Some((lhs, rhs)) = parse_line(format, &line) else { continue };
+        let (lhs, rhs) = match (lhs.parse::<u64>(), rhs.parse::<u64>()) {
+            (Ok(lhs), Ok(rhs)) => (lhs, rhs),
+            // allow a header row in CSV files
+            _ if format == Format::Csv && line_number == 0 => continue,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: expected two numeric IDs, found '{line}'", line_number + 1),
+                ))
+            }
+        };
+
+        if lhs == rhs || !seen.insert((lhs.min(rhs), lhs.max(rhs))) {
+            summary.skipped += 1;
+            continue;
+        }
+
+        let lhs = get_or_put_person(&mut writer, &mut persons, lhs);
+        let rhs = get_or_put_person(&mut writer, &mut persons, rhs);
+        let rel = Thing { type_: FRIENDSHIP, thing_id: ThingID { id: thread_rng().gen() } };
+        writer.put_relation(rel, [(FRIEND, lhs), (FRIEND, rhs)]);
+        summary.friendships += 1;
+
+        pending += 1;
+        if pending == batch_size {
+            storage.commit(writer).map_err(io::Error::other)?;
+            writer = storage.writer().with_durability(durability);
+            pending = 0;
+        }
+    }
+    storage.commit(writer).map_err(io::Error::other)?;
+
+    summary.persons = persons.len();
+    Ok(summary)
+}
+
+fn parse_line(format: Format, line: &str) -> Option<(&str, &str)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let mut fields: Box<dyn Iterator<Item = &str>> = match format {
+        Format::Csv => Box::new(line.split(',').map(str::trim)),
+        Format::Snap => Box::new(line.split_whitespace()),
+    };
+    Some((fields.next().unwrap_or_default(), fields.next().unwrap_or_default()))
+}
+
+fn get_or_put_person(writer: &mut WriteHandle, persons: &mut HashMap<u64, Thing>, external_id: u64) -> Thing {
+    match persons.entry(external_id) {
+        Entry::Occupied(entry) => *entry.get(),
+        Entry::Vacant(entry) => {
+            // assume collisions unlikely
+            let person = Thing { type_: PERSON, thing_id: ThingID { id: thread_rng().gen() } };
+            let name = Attribute { type_: NAME, value: external_id };
+            writer.put_entity(person);
+            writer.put_attribute(name);
+            writer.put_ownership(person, name);
+            *entry.insert(person)
+        }
+    }
+}
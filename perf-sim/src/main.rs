@@ -1,25 +1,79 @@
 mod agent;
 mod concept;
+mod crash_test;
+mod export;
+mod fault_test;
+mod import;
+mod open_loop;
 mod storage;
 
 use std::{
-    path::PathBuf,
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    process,
     str::FromStr,
-    sync::atomic::{AtomicBool, Ordering},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use clap::{arg, command, value_parser, ArgAction};
+use bench_utils::{
+    affinity::Placement,
+    faults::Faults,
+    interrupt, memory,
+    progress::{self, ActiveThreads, LatencyHistogram, Rate},
+    sweep::Sweep,
+    trials::{Trial, Trials},
+    units::Bytes,
+    warmup::Warmup,
+};
+use clap::{
+    arg, builder::RangedU64ValueParser, command, parser::ValueSource, value_parser, ArgAction, ArgMatches, Command,
+};
 use itertools::Itertools;
 
-use self::{concept::Attribute, storage::Storage};
+use self::{
+    agent::{AgentKind, AgentMix, AgentOptions, AgentStats, ArityReport, OperationOptions},
+    storage::{Durability, ErrorCounts, Reads, Siblings, Storage},
+};
+
+/// The arguments that results of a run are recorded under.
+const RUN_PARAMS: [&str; 19] = [
+    "mode",
+    "agent",
+    "attachments",
+    "arity",
+    "threads",
+    "pin",
+    "range-readers",
+    "batch-reads",
+    "snapshots",
+    "no-degrees",
+    "attribute-index",
+    "read-own-writes",
+    "siblings",
+    "durability",
+    "faults",
+    "target-ops-per-sec",
+    "arrivals",
+    "seconds",
+    "warmup",
+];
+
+/// What `fault-test` injects without `--faults`.
+const DEFAULT_TEST_FAULTS: &str = "write=0.05,read=0.01";
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 enum Mode {
     SingleColumnFamily,
     MultipleColumnFamilies,
+    TypeColumnFamilies,
     MultipleDatabases,
+    Sharded(usize),
 }
 
 impl FromStr for Mode {
@@ -28,84 +82,580 @@ impl FromStr for Mode {
         match s {
             "SINGLE" => Ok(Self::SingleColumnFamily),
             "CF" => Ok(Self::MultipleColumnFamilies),
+            "TYPE" => Ok(Self::TypeColumnFamilies),
             "DB" => Ok(Self::MultipleDatabases),
-            s => Err(format!("Unexpected mode argument: '{s}'. Expected SINGLE, CF, or DB.")),
+            s => match s.strip_prefix("SHARD:").map(str::parse) {
+                Some(Ok(shard_count)) if shard_count > 0 => Ok(Self::Sharded(shard_count)),
+                _ => Err(format!("Unexpected mode argument: '{s}'. Expected SINGLE, CF, TYPE, DB, or SHARD:N.")),
+            },
+        }
+    }
+}
+
+impl Display for Mode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SingleColumnFamily => write!(f, "SINGLE"),
+            Self::MultipleColumnFamilies => write!(f, "CF"),
+            Self::TypeColumnFamilies => write!(f, "TYPE"),
+            Self::MultipleDatabases => write!(f, "DB"),
+            Self::Sharded(shard_count) => write!(f, "SHARD:{shard_count}"),
         }
     }
 }
 
 fn main() {
-    let args = command!()
-        .arg(arg!(-b --"batch-reads" "Try to batch reads before writes").required(false).action(ArgAction::SetTrue))
-        .arg(
-            arg!(-t --threads "Number of writer threads")
-                .required(false)
-                .action(ArgAction::Set)
-                .value_parser(value_parser!(usize))
-                .default_value("4"),
-        )
-        .arg(
-            arg!(-m --mode <MODE> "SINGLE (default) / CF / DB")
-                .value_parser(value_parser!(Mode))
-                .default_value("SINGLE"),
+    let args = cli().get_matches();
+
+    let mode = get_arg(&args, "mode");
+    let storage_dir = get_arg::<PathBuf>(&args, "dir");
+
+    if let Some(("export", args)) = args.subcommand() {
+        let storage = Storage::open_read_only(&storage_dir, mode)
+            .unwrap_or_else(|err| panic!("could not export: {err} (was the store created with --mode {mode}?)"));
+        let format = get_arg(args, "format");
+        let result = match args.get_one::<PathBuf>("output") {
+            Some(path) => {
+                let mut out = BufWriter::new(File::create(path).expect("could not create output file"));
+                export::export(&storage, format, &mut out).and_then(|()| out.flush())
+            }
+            None => export::export(&storage, format, &mut io::stdout().lock()),
+        };
+        result.expect("could not write export");
+        return;
+    }
+
+    if let Some(("crash-writer", _)) = args.subcommand() {
+        crash_test::crash_writer(&storage_dir, mode, get_arg(&args, "durability")).expect("crash writer failed");
+        return;
+    }
+
+    if let Some(("crash-test", args)) = args.subcommand() {
+        let consistent = crash_test::run(
+            &storage_dir,
+            &args.get_many::<Mode>("modes").unwrap().copied().collect_vec(),
+            &args.get_many::<Durability>("durabilities").unwrap().copied().collect_vec(),
+            get_arg(args, "iterations"),
+            Duration::from_millis(get_arg(args, "kill-within")),
         )
-        .arg(
-            arg!(-d --dir <DIR> "storage directory (default: ./testing-store)")
-                .value_parser(value_parser!(PathBuf))
-                .default_value("testing-store"),
+        .expect("could not run crash test");
+        if !consistent {
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(("fault-test", sub_args)) = args.subcommand() {
+        let faults = args.get_one::<Faults>("faults").cloned().unwrap_or_else(|| DEFAULT_TEST_FAULTS.parse().unwrap());
+        let consistent = fault_test::run(
+            &storage_dir,
+            &sub_args.get_many::<Mode>("modes").unwrap().copied().collect_vec(),
+            get_arg(&args, "durability"),
+            &faults,
+            get_arg(sub_args, "operations"),
         )
-        .arg(
-            arg!(-s --seconds <SECONDS> "how long to run the benchmark for")
-                .value_parser(value_parser!(u64))
-                .default_value("1"),
+        .expect("could not run fault test");
+        if !consistent {
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(("import", args)) = args.subcommand() {
+        let storage = Storage::new(&storage_dir, mode).expect("could not create storage");
+        let input = File::open(get_arg::<PathBuf>(args, "input")).expect("could not open input file");
+        let start = Instant::now();
+        let summary = import::import(
+            &storage,
+            get_arg(args, "format"),
+            BufReader::new(input),
+            get_arg::<NonZeroUsize>(args, "batch-size").get(),
+            get_arg(args, "durability"),
         )
-        .get_matches();
+        .expect("could not import edge list");
+        let elapsed = start.elapsed();
+        println!(
+            "Imported {} persons and {} friendships ({} edges skipped) in {elapsed:.2?}. Rate: {:.2} friendships/sec",
+            summary.persons,
+            summary.friendships,
+            summary.skipped,
+            summary.friendships as f64 / elapsed.as_secs_f64(),
+        );
+        storage.print_stats().expect("could not read storage stats");
+        return;
+    }
 
-    let mode = get_arg(&args, "mode");
-    let storage_dir = get_arg::<PathBuf>(&args, "dir");
-    let storage = Storage::new(&storage_dir, mode);
-
-    let num_threads = get_arg::<usize>(&args, "threads");
-    let batch_reads = args.get_one("batch-reads").copied().unwrap_or(false);
-
-    #[rustfmt::skip]
-    let supernodes = [
-        0xADE1A1DE,  0xADE1A1DE,  0xADE1A1DE,  0xADE1A1DE,  0xADE1A1DE,
-        0xBAA1,      0xBAA1,      0xBAA1,      0xBAA1,
-        0xB0BB1E,    0xB0BB1E,    0xB0BB1E,
-        0xDEBB1E,    0xDEBB1E,    0xDEBB1E,
-        0x01AF,      0x01AF,
-        0xC0FFEE,    0xC0FFEE,
-        0x0DDBA11,
-        0xB01DFACE,
-    ]
-    .into_iter()
-    .map(|value| Attribute { type_: agent::NAME, value })
-    .collect_vec();
+    interrupt::install_handler();
+
+    if let Some(("sweep", sweep_args)) = args.subcommand() {
+        let sweep = sweep_args.get_one::<Sweep>("SPEC").unwrap();
+        sweep.check_axes(&RUN_PARAMS).unwrap_or_else(|err| panic!("{err}"));
+        let combinations = sweep.combinations();
+        // parse every combination up front, so that a bad value fails before anything has run
+        let runs = combinations
+            .iter()
+            .map(|combination| {
+                cli().try_get_matches_from(sweep_argv(&args, combination)).unwrap_or_else(|err| err.exit())
+            })
+            .collect_vec();
+
+        let mut trials =
+            Trials::new(env!("CARGO_PKG_NAME"), get_arg(&args, "trials")).shuffled(args.get_flag("shuffle"));
+        for (combination, run_args) in combinations.iter().zip(&runs) {
+            let name = combination.iter().map(|(axis, value)| format!("{axis}={value}")).join(" ");
+            let storage_dir = &storage_dir;
+            trials.case_with_params(name, run_params(run_args), "ops/sec", move || {
+                run(storage_dir, get_arg(run_args, "mode"), run_args)
+            });
+        }
+        let summaries = trials.run().into_iter().map(|(_, summary)| summary);
+        let results =
+            combinations.into_iter().zip(summaries).filter_map(|(combination, summary)| Some((combination, summary?)));
+        sweep.print_table("ops/sec", &results.collect_vec());
+        interrupt::remove_interrupted_store(&storage_dir);
+        return;
+    }
+
+    let mut trials = Trials::new(env!("CARGO_PKG_NAME"), get_arg(&args, "trials"));
+    trials.case_with_params("agents", run_params(&args), "ops/sec", || run(&storage_dir, mode, &args));
+    trials.run();
+    interrupt::remove_interrupted_store(&storage_dir);
+}
+
+/// The run arguments as they are recorded with the results.
+fn run_params(args: &ArgMatches) -> impl Iterator<Item = (&'static str, String)> + '_ {
+    RUN_PARAMS.into_iter().filter_map(|id| {
+        let values = args.get_raw(id)?.map(|value| value.to_string_lossy()).join(",");
+        Some((id, values))
+    })
+}
+
+/// The command line of one combination of a sweep: the run arguments that were given on the command line, with the
+/// combination's values in place of any it sets.
+fn sweep_argv(args: &ArgMatches, combination: &BTreeMap<String, String>) -> Vec<String> {
+    let mut argv = vec![env!("CARGO_PKG_NAME").to_owned()];
+    for arg in cli().get_arguments() {
+        let (id, Some(long)) = (arg.get_id().as_str(), arg.get_long()) else { continue };
+        let value = match combination.get(id) {
+            Some(value) => value.clone(),
+            None if args.value_source(id) == Some(ValueSource::CommandLine) => {
+                args.get_raw(id).unwrap().map(|value| value.to_string_lossy()).join(",")
+            }
+            None => continue,
+        };
+        if arg.get_action().takes_values() {
+            argv.extend([format!("--{long}"), value]);
+        } else if value == "true" {
+            argv.push(format!("--{long}"));
+        }
+    }
+    argv
+}
+
+fn cli() -> Command {
+    command!()
+    .arg(arg!(-b --"batch-reads" "Try to batch reads before writes").required(false).action(ArgAction::SetTrue))
+    .arg(
+        arg!(--snapshots "Read from a snapshot taken at the start of each iteration")
+            .required(false)
+            .action(ArgAction::SetTrue),
+    )
+    .arg(
+        arg!(--"no-degrees" "Don't maintain degree counters, to measure what they cost")
+            .required(false)
+            .action(ArgAction::SetTrue),
+    )
+    .arg(
+        arg!(--"attribute-index" "Give every person an age and index attribute values by value, for --range-readers; \
+             adds 5 keys per person, and in SHARD mode sends every registration to the shards of the indexes")
+            .required(false)
+            .action(ArgAction::SetTrue),
+    )
+    .arg(
+        arg!(--"read-own-writes" "Index the writes of each iteration, so that its reads see them, to measure what that \
+             costs")
+            .required(false)
+            .action(ArgAction::SetTrue),
+    )
+    .arg(
+        arg!(--siblings <SIBLINGS> "always / never / below:N: which relations get sibling edges, by their number of \
+             players; reads of the others traverse relates edges")
+            .value_parser(value_parser!(Siblings))
+            .default_value("always"),
+    )
+    .arg(
+        arg!(--"target-ops-per-sec" <RATE> "Schedule operations at this rate across the agent threads (open loop)")
+            .value_parser(value_parser!(f64)),
+    )
+    .arg(
+        arg!(--arrivals <PROCESS> "fixed / poisson arrivals of the open loop schedule")
+            .value_parser(value_parser!(open_loop::Arrivals))
+            .default_value("fixed"),
+    )
+    .arg(
+        arg!(--agent <AGENTS> "social / ingest / preferential / reader / gathering agents to run, with a thread count each to mix \
+             them (e.g. 'social:3,reader:2'); an agent without a count runs on --threads threads")
+            .value_parser(value_parser!(AgentMix))
+            .default_value("social"),
+    )
+    .arg(
+        arg!(--attachments <M> "how many existing persons a preferential agent befriends each new person with, picked \
+             in proportion to their degree (so they need degree counters)")
+            .value_parser(value_parser!(usize))
+            .default_value("3"),
+    )
+    .arg(
+        arg!(--arity <N> "numbers of players that a gathering agent picks from at random for each gathering, at \
+             least 2")
+            .value_parser(RangedU64ValueParser::<usize>::new().range(2..))
+            .value_delimiter(',')
+            .default_value("3,10,25,50"),
+    )
+    .arg(
+        arg!(-t --threads "Number of agent threads")
+            .required(false)
+            .action(ArgAction::Set)
+            .value_parser(value_parser!(usize))
+            .default_value("4"),
+    )
+    .arg(
+        arg!(--pin <PLACEMENT> "os / round-robin / compact / CPU list (e.g. 0,2,4-7) to pin the threads to")
+            .value_parser(value_parser!(Placement))
+            .default_value("os"),
+    )
+    .arg(
+        arg!(--"range-readers" <THREADS> "Number of threads running age range queries alongside the agents")
+            .requires("attribute-index")
+            .value_parser(value_parser!(usize))
+            .default_value("0"),
+    )
+    .arg(
+        arg!(-m --mode <MODE> "SINGLE (default) / CF / TYPE / DB / SHARD:N")
+            .value_parser(value_parser!(Mode))
+            .default_value("SINGLE")
+            .global(true),
+    )
+    .arg(
+        arg!(-d --dir <DIR> "storage directory (default: ./testing-store)")
+            .value_parser(value_parser!(PathBuf))
+            .default_value("testing-store")
+            .global(true),
+    )
+    .arg(
+        arg!(--durability <DURABILITY> "none (default) / wal / sync: how far commits make it to disk before returning")
+            .value_parser(value_parser!(Durability))
+            .default_value("none")
+            .global(true),
+    )
+    .arg(
+        arg!(--faults <SPEC> "fail storage calls, e.g. 'write=0.01,read=every:100,sync=after:5000' (points: write, \
+             read, sync; schedules: a probability, every:N, after:N)")
+            .value_parser(value_parser!(Faults))
+            .global(true),
+    )
+    .arg(
+        arg!(-s --seconds <SECONDS> "how long to measure for, after the warm-up")
+            .value_parser(value_parser!(u64))
+            .default_value("1"),
+    )
+    .arg(
+        arg!(--trials <TRIALS> "how many times to repeat the run, each against a fresh store")
+            .value_parser(value_parser!(usize))
+            .default_value("1"),
+    )
+    .arg(
+        arg!(--shuffle "interleave the trials of all sweep combinations, so that drift over the run doesn't favour the \
+             earlier ones")
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+        arg!(-w --warmup <SECONDS> "discard results for this many seconds, or until throughput settles with 'auto'")
+            .value_parser(value_parser!(Warmup))
+            .default_value("0"),
+    )
+    .subcommand(
+        Command::new("export")
+            .about("Export the graph in an existing store (persons and friendships)")
+            .arg(
+                arg!(-f --format <FORMAT> "graphml / dot / csv")
+                    .value_parser(value_parser!(export::Format))
+                    .default_value("graphml"),
+            )
+            .arg(arg!(-o --output <FILE> "output file (default: stdout)").value_parser(value_parser!(PathBuf))),
+    )
+    .subcommand(
+        Command::new("import")
+            .about("Load an external edge list into a fresh store as persons and friendships")
+            .arg(arg!(-i --input <FILE> "edge list to load").required(true).value_parser(value_parser!(PathBuf)))
+            .arg(
+                arg!(-f --format <FORMAT> "csv / snap")
+                    .value_parser(value_parser!(import::Format))
+                    .default_value("snap"),
+            )
+            .arg(
+                arg!(--"batch-size" <EDGES> "friendships per commit")
+                    .value_parser(value_parser!(NonZeroUsize))
+                    .default_value("10000"),
+            ),
+    )
+    .subcommand(
+        Command::new("crash-test")
+            .about("Kill a writer with SIGKILL mid-run, again and again, and check that no commit was torn or lost")
+            .arg(
+                arg!(--modes <MODES> "storage modes to test")
+                    .value_parser(value_parser!(Mode))
+                    .value_delimiter(',')
+                    .default_value("SINGLE,CF,TYPE,DB,SHARD:4"),
+            )
+            .arg(
+                arg!(--durabilities <DURABILITIES> "durability settings to test")
+                    .value_parser(value_parser!(Durability))
+                    .value_delimiter(',')
+                    .default_value("none,wal,sync"),
+            )
+            .arg(
+                arg!(-n --iterations <N> "crashes per mode and durability")
+                    .value_parser(value_parser!(usize))
+                    .default_value("10"),
+            )
+            .arg(
+                arg!(--"kill-within" <MILLIS> "kill the writer at a random point within this many milliseconds of its first commit")
+                    .value_parser(value_parser!(u64))
+                    .default_value("2000"),
+            ),
+    )
+    .subcommand(
+        Command::new("fault-test")
+            .about("Run agent operations with faults injected (--faults), and check that no failed commit was torn")
+            .arg(
+                arg!(--modes <MODES> "storage modes to test")
+                    .value_parser(value_parser!(Mode))
+                    .value_delimiter(',')
+                    .default_value("SINGLE,CF,TYPE,DB,SHARD:4"),
+            )
+            .arg(
+                arg!(-n --operations <N> "operations per mode")
+                    .value_parser(value_parser!(usize))
+                    .default_value("1000"),
+            ),
+    )
+    .subcommand(Command::new("crash-writer").about("Commit until killed, for crash-test").hide(true))
+    .subcommand(
+        Command::new("sweep")
+            .about("Run every combination of the given run arguments, each against a fresh store, and tabulate them")
+            .arg(
+                arg!(<SPEC> "axes to sweep over, e.g. 'threads=1,4 mode=SINGLE,CF'")
+                    .value_parser(value_parser!(Sweep)),
+            ),
+    )
+}
+
+/// Runs the agents against a fresh store and returns their steady-state throughput, with the memory used.
+fn run(storage_dir: &Path, mode: Mode, args: &ArgMatches) -> Trial {
+    let storage = Storage::new(storage_dir, mode)
+        .expect("could not create storage")
+        .with_siblings(get_arg::<Siblings>(args, "siblings"))
+        .with_snapshots(args.get_one("snapshots").copied().unwrap_or(false));
+
+    let agents = get_arg::<AgentMix>(args, "agent").threads(get_arg(args, "threads"));
+    let num_threads = agents.len();
+    let num_range_readers = get_arg::<usize>(args, "range-readers");
+    let options = OperationOptions {
+        batch_reads: args.get_one("batch-reads").copied().unwrap_or(false),
+        snapshots: args.get_one("snapshots").copied().unwrap_or(false),
+        degrees: !args.get_one("no-degrees").copied().unwrap_or(false),
+        attribute_index: args.get_one("attribute-index").copied().unwrap_or(false),
+        read_own_writes: args.get_one("read-own-writes").copied().unwrap_or(false),
+        durability: get_arg(args, "durability"),
+    };
+    let arities = args.get_many::<usize>("arity").unwrap().copied().collect_vec();
+    let agent_options = AgentOptions { attachments: get_arg(args, "attachments"), arities: &arities };
+    let arity_report = ArityReport::default();
+    let target_ops_per_sec = args.get_one::<f64>("target-ops-per-sec").copied();
+    let arrivals = get_arg::<open_loop::Arrivals>(args, "arrivals");
+    let warmup = get_arg::<Warmup>(args, "warmup");
+    let placement = get_arg::<Placement>(args, "pin").plan().expect("could not plan thread placement");
+    let mut thread_groups = Vec::new();
+    let mut first = 0;
+    for threads in agents.chunk_by(|a, b| a == b) {
+        thread_groups.push((format!("{} agents", threads[0]), first..first + threads.len()));
+        first += threads.len();
+    }
+    thread_groups.push(("range readers".to_owned(), num_threads..num_threads + num_range_readers));
+    let thread_groups = thread_groups.iter().map(|(name, threads)| (name.as_str(), threads.clone())).collect_vec();
+    println!("{}", placement.describe(&thread_groups));
+
+    let supernodes = agent::supernodes();
 
     let mut writer = storage.writer();
+    if options.attribute_index {
+        writer = writer.with_attribute_index();
+    }
     supernodes.iter().unique().for_each(|name| {
         agent::register_person(&mut writer, *name);
     });
-    storage.commit(writer);
+    storage.commit(writer).expect("could not register supernodes");
+    let storage = storage.with_faults(args.get_one::<Faults>("faults").cloned().unwrap_or_default());
 
     let stop = AtomicBool::new(false);
+    let operations = AtomicUsize::new(0);
+    let range_queries = AtomicUsize::new(0);
+    let errors = ErrorCounts::default();
+    let active = ActiveThreads::default();
+    let open_loop_latencies = LatencyHistogram::default();
+    let mut written_keys = Rate::new(storage.written_keys());
+    let status = |interval: Duration| {
+        let commits = storage.commit_latencies().take();
+        format!(
+            "{:.0} commits/sec, {:.0} keys/sec, {} threads, commit latency {commits}, pending compaction {}, stall: {}",
+            commits.count() as f64 / interval.as_secs_f64(),
+            written_keys.per_sec(interval),
+            active.count(),
+            Bytes(storage.pending_compaction_bytes().expect("could not read pending compaction")),
+            storage.write_stall().expect("could not read write stall"),
+        )
+    };
 
-    thread::scope(|s| {
-        for _ in 0..num_threads {
-            s.spawn({
-                let stop = &stop;
-                let supernodes = &supernodes;
-                let storage = &storage;
-                move || agent::agent(storage, stop, batch_reads, supernodes)
-            });
+    let start = Instant::now();
+    let ((warmup_end, interrupted_in_warmup, range_queries_at_warmup_end, agent_results), profile) = memory::track(
+        || storage.memory_usage().expect("could not read memory usage"),
+        || {
+            progress::track(status, || {
+                thread::scope(|s| {
+                    let agent_threads = (0..num_threads)
+                        .map(|thread_index| {
+                            let kind = agents[thread_index];
+                            let stop = &stop;
+                            let operations = &operations;
+                            let supernodes = &supernodes;
+                            let storage = &storage;
+                            let placement = &placement;
+                            let errors = &errors;
+                            let arity_report = &arity_report;
+                            let active = &active;
+                            let open_loop_latencies = &open_loop_latencies;
+                            s.spawn(move || {
+                                placement.pin(thread_index);
+                                let _active = active.enter();
+                                let mut agent = kind.make(options, agent_options, supernodes, arity_report);
+                                if !agent::setup(agent.as_mut(), storage, stop, errors) {
+                                    return (kind, agent.stats(), None);
+                                }
+                                let missed = match target_ops_per_sec {
+                                    Some(ops_per_sec) => Some(open_loop::run(
+                                        stop,
+                                        start,
+                                        arrivals,
+                                        ops_per_sec,
+                                        (thread_index, num_threads),
+                                        (operations, open_loop_latencies),
+                                        || agent.iteration(storage).map_err(|err| errors.record(&err)).is_ok(),
+                                    )),
+                                    None => {
+                                        agent::run(agent.as_mut(), storage, stop, operations, errors);
+                                        None
+                                    }
+                                };
+                                (kind, agent.stats(), missed)
+                            })
+                        })
+                        .collect_vec();
+                    for thread_index in num_threads..num_threads + num_range_readers {
+                        let stop = &stop;
+                        let range_queries = &range_queries;
+                        let storage = &storage;
+                        let placement = &placement;
+                        let errors = &errors;
+                        let active = &active;
+                        s.spawn(move || {
+                            placement.pin(thread_index);
+                            let _active = active.enter();
+                            agent::range_reader(storage, stop, range_queries, errors, options.snapshots)
+                        });
+                    }
+
+                    // agents finish the operation they are in when stopped, so an interrupted run still commits cleanly
+                    let warmup_end = warmup.wait(&operations, interrupt::interrupted);
+                    let interrupted_in_warmup = interrupt::interrupted();
+                    let range_queries_at_warmup_end = range_queries.load(Ordering::Relaxed);
+                    // drop the latencies of the warm-up
+                    open_loop_latencies.take();
+                    interrupt::sleep(Duration::from_secs(get_arg(args, "seconds")));
+                    stop.store(true, Ordering::Release);
+                    let agent_results = agent_threads.into_iter().map(|handle| handle.join().unwrap()).collect_vec();
+                    (warmup_end, interrupted_in_warmup, range_queries_at_warmup_end, agent_results)
+                })
+            })
+        },
+    );
+    let measured = warmup_end.at.elapsed();
+
+    println!("Warm-up: {:.2?}, discarding {} operations", warmup_end.elapsed, warmup_end.progress);
+    if !warmup_end.steady {
+        println!("Throughput did not settle during the warm-up");
+    }
+    let steady_operations = operations.load(Ordering::Relaxed) - warmup_end.progress;
+    if interrupted_in_warmup {
+        // the trial is discarded, but what the agents did up to here is still reported
+        println!("Interrupted during the warm-up, so nothing was measured");
+    } else {
+        println!(
+            "Measured {steady_operations} operations in {measured:.2?} ({:.2} ops/sec)",
+            steady_operations as f64 / measured.as_secs_f64()
+        );
+    }
+
+    print_agent_stats(&agent_results);
+    arity_report.print();
+    if let Some(ops_per_sec) = target_ops_per_sec {
+        let missed = agent_results.iter().filter_map(|(_, _, missed)| *missed).sum();
+        open_loop::report(ops_per_sec, warmup_end.at, open_loop_latencies.take(), missed);
+    }
+
+    if num_range_readers > 0 {
+        let range_queries = range_queries.load(Ordering::Relaxed) - range_queries_at_warmup_end;
+        println!("Range queries: {range_queries} ({:.2} queries/sec)", range_queries as f64 / measured.as_secs_f64());
+    }
+
+    errors.print();
+    if storage.faults().is_enabled() {
+        println!("Injected faults: {}", storage.faults().summary());
+    }
+    // report without faults, so that the reads are reliable
+    let storage = storage.with_faults(Faults::default());
+
+    if options.degrees {
+        let snapshot = storage.snapshot();
+        for name in supernodes.iter().unique() {
+            if let Some(person) = storage.get_one_owner(&snapshot, name).expect("could not read supernode") {
+                let degree =
+                    storage.degree(&snapshot, person, agent::FRIEND, agent::FRIENDSHIP).expect("could not read degree");
+                println!("Supernode {:#X} has {degree} friendships", { name.value });
+            }
         }
+    }
 
-        thread::sleep(Duration::from_secs(get_arg(&args, "seconds")));
-        stop.store(true, Ordering::Release);
-    });
+    storage.print_stats().expect("could not read storage stats");
+    memory::print_peaks([&profile]);
 
-    storage.print_stats();
+    Trial::from((steady_operations as f64 / measured.as_secs_f64(), profile))
+}
+
+/// Prints what the threads of each agent did, summed over the threads and over the whole run, warm-up included.
+fn print_agent_stats(results: &[(AgentKind, AgentStats, Option<usize>)]) {
+    for threads in results.chunk_by(|a, b| a.0 == b.0) {
+        let mut totals = Vec::<(&str, u64)>::new();
+        for (_, stats, _) in threads {
+            for &(name, count) in stats {
+                match totals.iter_mut().find(|(total_name, _)| *total_name == name) {
+                    Some((_, total)) => *total += count,
+                    None => totals.push((name, count)),
+                }
+            }
+        }
+        let totals = totals.into_iter().map(|(name, count)| format!("{count} {name}")).join(", ");
+        println!("{} agents ({} threads): {totals}", threads[0].0, threads.len());
+    }
 }
 
 fn get_arg<T: Clone + Send + Sync + 'static>(args: &clap::ArgMatches, key: &str) -> T {
@@ -0,0 +1,94 @@
+use std::{
+    str::FromStr,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bench_utils::progress::{LatencyCounts, LatencyHistogram};
+use rand::{thread_rng, Rng};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Arrivals {
+    Fixed,
+    Poisson,
+}
+
+impl FromStr for Arrivals {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fixed" => Ok(Self::Fixed),
+            "poisson" => Ok(Self::Poisson),
+            s => Err(format!("Unexpected arrival process: '{s}'. Expected fixed or poisson.")),
+        }
+    }
+}
+
+/// Runs `operation` on schedule until stopped, for thread `thread_index` of `thread_count` that together offer
+/// `ops_per_sec`. Fixed arrivals are staggered across threads so that the combined schedule is evenly spaced too.
+/// Returns how many scheduled operations the thread never got to before being stopped.
+///
+/// Latency is measured from the scheduled start rather than the actual one, so that time spent queued behind a slow
+/// operation counts against the ones it delayed, and recorded into `latencies`, which the threads share. `operation`
+/// returns whether it succeeded; failed operations still have their latency recorded, but are not counted in
+/// `operations`.
+pub fn run(
+    stop: &AtomicBool,
+    start: Instant,
+    arrivals: Arrivals,
+    ops_per_sec: f64,
+    (thread_index, thread_count): (usize, usize),
+    (operations, latencies): (&AtomicUsize, &LatencyHistogram),
+    mut operation: impl FnMut() -> bool,
+) -> usize {
+    let rate = ops_per_sec / thread_count as f64;
+    let mut intended = start + Duration::from_secs_f64(thread_index as f64 / ops_per_sec);
+    loop {
+        if arrivals == Arrivals::Poisson {
+            // exponentially distributed gaps, from inverting the CDF on a uniform sample in (0, 1]
+            intended += Duration::from_secs_f64(-(1.0 - thread_rng().gen::<f64>()).ln() / rate);
+        }
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+        if let Some(wait) = intended.checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        }
+        let succeeded = operation();
+        latencies.record(intended.elapsed());
+        if succeeded {
+            operations.fetch_add(1, Ordering::Relaxed);
+        }
+        if arrivals == Arrivals::Fixed {
+            intended += Duration::from_secs_f64(1.0 / rate);
+        }
+    }
+    // whatever was scheduled before the stop but never started is what a closed-loop run would silently drop. Poisson
+    // arrivals are counted at their mean rate.
+    let now = Instant::now();
+    let mut missed = 0;
+    while intended < now {
+        missed += 1;
+        intended += Duration::from_secs_f64(1.0 / rate);
+    }
+    missed
+}
+
+/// Prints the latency distribution of all threads against the load that was offered, given the `latencies` of the
+/// operations that finished from `measured_from` on and the operations that `missed` their schedule. Percentiles are
+/// the lower bounds of their histogram buckets.
+pub fn report(offered_ops_per_sec: f64, measured_from: Instant, latencies: LatencyCounts, missed: usize) {
+    let achieved = latencies.count() as f64 / measured_from.elapsed().as_secs_f64();
+    println!("Offered load: {offered_ops_per_sec:.2} ops/sec, achieved: {achieved:.2} ops/sec, missed: {missed} ops");
+    let (Some(p50), Some(p90), Some(p99), Some(p999), Some(max)) = (
+        latencies.percentile(0.5),
+        latencies.percentile(0.9),
+        latencies.percentile(0.99),
+        latencies.percentile(0.999),
+        latencies.percentile(1.0),
+    ) else {
+        return;
+    };
+    println!("Latency: p50 {p50:.2?}, p90 {p90:.2?}, p99 {p99:.2?}, p99.9 {p999:.2?}, max {max:.2?}");
+}
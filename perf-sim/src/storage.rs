@@ -1,11 +1,40 @@
-use std::path::Path;
+use std::{
+    cmp,
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io,
+    mem::size_of,
+    ops::Range,
+    path::{Path, PathBuf},
+    ptr,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, RwLock,
+    },
+    time::Instant,
+};
 
-use itertools::Itertools;
-use rand::{thread_rng, Rng};
-use speedb::{ColumnFamily, IteratorMode, Options, WriteBatch, DB, DEFAULT_COLUMN_FAMILY_NAME};
+use bench_utils::{
+    disk::{self, DiskUsage},
+    faults::{Fault, Faults, Point},
+    progress::{LatencyHistogram, Stall},
+    units::Bytes,
+};
+use itertools::{EitherOrBoth, Itertools};
+use rand::{seq::SliceRandom, thread_rng, Rng};
+use speedb::{
+    ColumnFamily, ColumnFamilyDescriptor, Direction, IteratorMode, MergeOperands, Options, ReadOptions, Snapshot,
+    WriteBatch, WriteOptions, DB, DEFAULT_COLUMN_FAMILY_NAME,
+};
 
 use crate::{
-    concept::{Attribute, EdgeType, HasEdge, RelatesEdge, RelationSiblingEdge, Thing, ThingID, Type},
+    agent::SCHEMA,
+    concept::{
+        Attribute, AttributeIndexEntry, AttributeType, DegreeKey, EdgeType, HasEdge, Prefix, RelatesEdge,
+        RelationSiblingEdge, Thing, ThingID, Type,
+    },
     Mode,
 };
 
@@ -16,10 +45,264 @@ const HAS_BACKWARD: &str = "has_backward";
 const RELATES_FORWARD: &str = "relates_forward";
 const RELATES_BACKWARD: &str = "relates_backward";
 const RELATION_SIBLING: &str = "relation_sibling";
-const CFS: [&str; 7] =
-    [THING, ATTRIBUTE, HAS_FORWARD, HAS_BACKWARD, RELATES_FORWARD, RELATES_BACKWARD, RELATION_SIBLING];
+const ATTRIBUTE_INDEX: &str = "attribute_index";
+const DEGREE: &str = "degree";
+const CFS: [&str; 9] = [
+    THING,
+    ATTRIBUTE,
+    HAS_FORWARD,
+    HAS_BACKWARD,
+    RELATES_FORWARD,
+    RELATES_BACKWARD,
+    RELATION_SIBLING,
+    ATTRIBUTE_INDEX,
+    DEGREE,
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum KeySpace {
+    Thing,
+    Attribute,
+    HasForward,
+    HasBackward,
+    RelatesForward,
+    RelatesBackward,
+    RelationSibling,
+    AttributeIndex,
+    Degree,
+}
+
+impl KeySpace {
+    pub const ALL: [Self; 9] = [
+        Self::Thing,
+        Self::Attribute,
+        Self::HasForward,
+        Self::HasBackward,
+        Self::RelatesForward,
+        Self::RelatesBackward,
+        Self::RelationSibling,
+        Self::AttributeIndex,
+        Self::Degree,
+    ];
+
+    /// Recovers the key space of a key from its layout, for when all key spaces share one column family.
+    ///
+    /// Forward and backward relates edges have the same shape; a key leading with a relation is taken to be a
+    /// forward edge, which is exact as long as relations don't play roles themselves.
+    pub fn of(key: &[u8]) -> Option<Self> {
+        if key.first() == Some(&(Prefix::AttributeIndex as u8)) {
+            return (key.len() == AttributeIndexEntry::encoding_size()).then_some(Self::AttributeIndex);
+        }
+        if key.first() == Some(&(Prefix::Attribute as u8)) {
+            return match key.len() {
+                len if len == size_of::<Attribute>() => Some(Self::Attribute),
+                len if len == HasEdge::backward_encoding_size() => Some(Self::HasBackward),
+                _ => None,
+            };
+        }
+        if key.len() == size_of::<Thing>() {
+            return Some(Self::Thing);
+        }
+        match key.get(size_of::<Thing>()) {
+            Some(&edge_type) if edge_type == EdgeType::Has as u8 => Some(Self::HasForward),
+            Some(&edge_type) if edge_type == EdgeType::Relates as u8 => {
+                if key[0] == Prefix::Relation as u8 {
+                    Some(Self::RelatesForward)
+                } else {
+                    Some(Self::RelatesBackward)
+                }
+            }
+            Some(&edge_type) if edge_type == EdgeType::Sibling as u8 => Some(Self::RelationSibling),
+            Some(&edge_type) if edge_type == EdgeType::Degree as u8 => Some(Self::Degree),
+            _ => None,
+        }
+    }
+}
+
+/// How far a commit has made it to disk by the time it returns.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Durability {
+    /// Skips the write-ahead log, so commits are only on disk once their memtables are flushed.
+    None,
+    /// Writes the write-ahead log without syncing it: commits survive the process crashing, but not the machine.
+    Wal,
+    /// Syncs the write-ahead log before every commit returns.
+    Sync,
+}
+
+impl FromStr for Durability {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "wal" => Ok(Self::Wal),
+            "sync" => Ok(Self::Sync),
+            s => Err(format!("Unexpected durability: '{s}'. Expected none, wal, or sync.")),
+        }
+    }
+}
+
+impl Display for Durability {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Wal => write!(f, "wal"),
+            Self::Sync => write!(f, "sync"),
+        }
+    }
+}
+
+/// Which relations `WriteHandle::put_relation` writes sibling edges for. Reads of siblings that weren't written
+/// traverse the relates edges instead.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Siblings {
+    #[default]
+    Always,
+    Never,
+    /// Only for relations with fewer players than this.
+    Below(usize),
+}
+
+impl Siblings {
+    fn materialised(self, arity: usize) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::Below(threshold) => arity < threshold,
+        }
+    }
+}
+
+impl FromStr for Siblings {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            s => match s.strip_prefix("below:").map(str::parse) {
+                Some(Ok(threshold)) => Ok(Self::Below(threshold)),
+                _ => Err(format!("Unexpected siblings: '{s}'. Expected always, never, or below:N.")),
+            },
+        }
+    }
+}
+
+impl Display for Siblings {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Always => write!(f, "always"),
+            Self::Never => write!(f, "never"),
+            Self::Below(threshold) => write!(f, "below:{threshold}"),
+        }
+    }
+}
+
+/// What can go wrong with the storage. Every failure of a call into the databases comes back as one of these.
+#[derive(Debug)]
+pub enum StorageError {
+    Open {
+        path: PathBuf,
+        source: speedb::Error,
+    },
+    /// Clearing or measuring the storage directory.
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Write(speedb::Error),
+    Read(speedb::Error),
+    Iterate(speedb::Error),
+    /// A key or value without the layout of its key space.
+    Decode {
+        key_space: KeySpace,
+        bytes: Box<[u8]>,
+    },
+    Injected(Fault),
+}
+
+impl StorageError {
+    /// What errors are counted by.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Open { .. } => "open",
+            Self::Io { .. } => "io",
+            Self::Write(_) => "write",
+            Self::Read(_) => "read",
+            Self::Iterate(_) => "iterate",
+            Self::Decode { .. } => "decode",
+            Self::Injected(_) => "injected",
+        }
+    }
+}
+
+impl Display for StorageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Open { path, source } => write!(f, "could not open database at {}: {source}", path.display()),
+            Self::Io { path, source } => write!(f, "could not access {}: {source}", path.display()),
+            Self::Write(source) => write!(f, "could not write: {source}"),
+            Self::Read(source) => write!(f, "could not read: {source}"),
+            Self::Iterate(source) => write!(f, "could not iterate: {source}"),
+            Self::Decode { key_space, bytes } => write!(f, "could not decode {key_space:?} entry {bytes:02x?}"),
+            Self::Injected(fault) => write!(f, "{fault}"),
+        }
+    }
+}
 
-pub enum Storage {
+impl Error for StorageError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Open { source, .. } | Self::Write(source) | Self::Read(source) | Self::Iterate(source) => {
+                Some(source)
+            }
+            Self::Io { source, .. } => Some(source),
+            Self::Decode { .. } => None,
+            Self::Injected(fault) => Some(fault),
+        }
+    }
+}
+
+/// The errors that failed operations, counted by kind, with the first error of each kind to show what went wrong.
+#[derive(Debug, Default)]
+pub struct ErrorCounts {
+    counts: Mutex<BTreeMap<&'static str, (usize, String)>>,
+}
+
+impl ErrorCounts {
+    pub fn record(&self, error: &StorageError) {
+        let mut counts = self.counts.lock().unwrap();
+        counts.entry(error.kind()).or_insert_with(|| (0, error.to_string())).0 += 1;
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.lock().unwrap().values().map(|&(count, _)| count).sum()
+    }
+
+    /// Prints a line per kind of error, if there were any.
+    pub fn print(&self) {
+        let total = self.total();
+        if total == 0 {
+            return;
+        }
+        println!("Errors: {total}");
+        for (kind, (count, first)) in self.counts.lock().unwrap().iter() {
+            println!("  {kind:<8} {count:>10} (first: {first})");
+        }
+    }
+}
+
+/// How much of the storage some of its keys take up.
+#[derive(Copy, Clone, Debug, Default)]
+struct SpaceUsage {
+    keys: usize,
+    /// The bytes of the keys and values themselves.
+    logical_bytes: u64,
+    /// Estimated for key spaces that share a column family.
+    sst_bytes: u64,
+}
+
+/// How the key spaces are laid out over databases and column families, one variant per `Mode`.
+enum Layout {
     Single(SingleDB),
     MultipleColumnFamilies {
         db: DB,
@@ -30,6 +313,15 @@ pub enum Storage {
         relates_forward_cf: &'static ColumnFamily,
         relates_backward_cf: &'static ColumnFamily,
         relation_sibling_cf: &'static ColumnFamily,
+        attribute_index_cf: &'static ColumnFamily,
+        degree_cf: &'static ColumnFamily,
+    },
+    /// One column family per type that keys lead with, keyed by the encoding of that type. Keys leading with a type
+    /// outside of the schema go to the default column family.
+    TypeColumnFamilies {
+        db: DB,
+        type_cfs: HashMap<[u8; size_of::<Type>()], &'static ColumnFamily>,
+        default_cf: &'static ColumnFamily,
     },
     MultipleDatabases {
         thing_db: SingleDB,
@@ -39,7 +331,52 @@ pub enum Storage {
         relates_forward_db: SingleDB,
         relates_backward_db: SingleDB,
         relation_sibling_db: SingleDB,
+        attribute_index_db: SingleDB,
+        degree_db: SingleDB,
+        /// Held shared by commits, if the storage is `with_snapshots`, and exclusively while snapshotting, so a snapshot
+        /// never sees half a commit.
+        commit_lock: RwLock<()>,
     },
+    /// Every key lives in the shard of the thing or attribute it leads with, so forward edges are stored with their
+    /// source and backward edges with their target.
+    Sharded {
+        shards: Vec<SingleDB>,
+        commit_lock: RwLock<()>,
+    },
+}
+
+pub struct Storage {
+    layout: Layout,
+    /// Failures to inject into calls to the databases, which fail with `StorageError::Injected`.
+    faults: Faults,
+    /// Which relations have sibling edges. Has to stay the same for the life of the store.
+    siblings: Siblings,
+    /// Whether commits take the commit lock in DB and SHARD modes, which snapshots need to be consistent.
+    snapshots: bool,
+    /// How long successful commits took, since the last time they were taken.
+    commit_latencies: LatencyHistogram,
+    /// Writes, merges, and deletes in successful commits.
+    written_keys: AtomicUsize,
+}
+
+/// A consistent view of the storage for reads. `Latest` reads whatever has been committed at the time of each read.
+pub enum ReadSnapshot<'a> {
+    Latest,
+    Single(Snapshot<'a>),
+    /// One snapshot per database, in the order of `Storage::dbs`.
+    Multi(Vec<Snapshot<'a>>),
+}
+
+impl ReadSnapshot<'_> {
+    fn read_options(&self, db_index: usize) -> ReadOptions {
+        let mut read_options = ReadOptions::default();
+        match self {
+            Self::Latest => (),
+            Self::Single(snapshot) => read_options.set_snapshot(snapshot),
+            Self::Multi(snapshots) => read_options.set_snapshot(&snapshots[db_index]),
+        }
+        read_options
+    }
 }
 
 pub struct SingleDB {
@@ -48,61 +385,132 @@ pub struct SingleDB {
 }
 
 impl SingleDB {
-    fn open(options: &Options, storage_dir: &Path) -> Self {
-        let db =
-            DB::open_cf(options, storage_dir, [DEFAULT_COLUMN_FAMILY_NAME]).expect("Could not create database storage");
-        unsafe { Self { cf: &*(db.cf_handle(DEFAULT_COLUMN_FAMILY_NAME).unwrap() as *const _), db } }
+    fn open(options: &Options, storage_dir: &Path, read_only: bool) -> Result<Self, StorageError> {
+        let db = open_db(options, storage_dir, [DEFAULT_COLUMN_FAMILY_NAME], read_only)?;
+        unsafe { Ok(Self { cf: &*(db.cf_handle(DEFAULT_COLUMN_FAMILY_NAME).unwrap() as *const _), db }) }
     }
 }
 
 /// SAFETY ???
-unsafe impl Sync for Storage {}
-
-fn exact_prefix_iterator_cf<'a>(
-    db: &'a DB,
-    cf: &ColumnFamily,
-    prefix: Vec<u8>,
-) -> impl Iterator<Item = std::boxed::Box<[u8]>> + 'a {
-    db.prefix_iterator_cf(cf, &prefix)
-        .filter_map(Result::ok)
-        .take_while(move |(k, _)| k.len() >= prefix.len() && k[0..prefix.len()] == prefix)
-        .map(|(k, _)| k)
-}
-
-fn exact_prefix_iterator_cf_from<'a>(
-    db: &'a DB,
-    cf: &ColumnFamily,
-    prefix: Vec<u8>,
-    start: &[u8],
-) -> impl Iterator<Item = std::boxed::Box<[u8]>> + 'a {
-    db.prefix_iterator_cf(cf, start)
-        .filter_map(Result::ok)
-        .take_while(move |(k, _)| k.len() >= prefix.len() && k[0..prefix.len()] == prefix)
-        .map(|(k, _)| k)
+unsafe impl Sync for Layout {}
+
+/// Picks the shard for a key by hashing the encoded thing, attribute, or indexed attribute type it starts with (FNV-1a,
+/// so that the placement is stable across builds and reopening a store finds every key where it was written).
+fn shard_index(key: &[u8], shard_count: usize) -> usize {
+    let leading_len = match key.first() {
+        Some(&prefix) if prefix == Prefix::Attribute as u8 => size_of::<Attribute>(),
+        // keep each attribute type's index in one shard, so that range scans don't have to visit every shard; with the
+        // attribute index on, every registration writes to the shards holding the name and age indexes, which makes
+        // them hot spots
+        Some(&prefix) if prefix == Prefix::AttributeIndex as u8 => size_of::<AttributeType>(),
+        _ => size_of::<Thing>(),
+    };
+    let hash = key[..leading_len.min(key.len())]
+        .iter()
+        .fold(0xcbf29ce484222325u64, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+    (hash % shard_count as u64) as usize
+}
+
+fn type_cf_name(type_: &Type) -> String {
+    format!("type_{}", type_.as_bytes().iter().map(|byte| format!("{byte:02x}")).join(""))
+}
+
+fn parse_type_cf_name(name: &str) -> Option<[u8; size_of::<Type>()]> {
+    let hex = name.strip_prefix("type_")?;
+    let mut bytes = [0; size_of::<Type>()];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(2 * i..2 * i + 2)?, 16).ok()?;
+    }
+    Some(bytes)
+}
+
+/// Attribute types encode their prefix and ID the same way as types do, so every key starts with a type encoding.
+fn type_cf<'a>(
+    type_cfs: &HashMap<[u8; size_of::<Type>()], &'a ColumnFamily>,
+    default_cf: &'a ColumnFamily,
+    key: &[u8],
+) -> &'a ColumnFamily {
+    key.get(..size_of::<Type>()).and_then(|leading_type| type_cfs.get(leading_type)).copied().unwrap_or(default_cf)
+}
+
+/// Column families only pick up the options they are opened with, and all of them need the degree merge operator.
+fn open_db(
+    options: &Options,
+    storage_dir: &Path,
+    cf_names: impl IntoIterator<Item = impl Into<String>>,
+    read_only: bool,
+) -> Result<DB, StorageError> {
+    let descriptors = cf_names.into_iter().map(|name| ColumnFamilyDescriptor::new(name, options.clone()));
+    let db = if read_only {
+        DB::open_cf_descriptors_read_only(options, storage_dir, descriptors, false)
+    } else {
+        DB::open_cf_descriptors(options, storage_dir, descriptors)
+    };
+    db.map_err(|source| StorageError::Open { path: storage_dir.to_owned(), source })
+}
+
+/// Degree counters are 8 bytes.
+fn decode_degree(value: &[u8]) -> Option<i64> {
+    Some(i64::from_le_bytes(value.try_into().ok()?))
+}
+
+/// Sums degree deltas, so that maintaining a counter never needs a read. A counter that doesn't decode fails the
+/// merge, which the database reports as corruption when the counter is read.
+fn add_degrees(_key: &[u8], existing: Option<&[u8]>, deltas: &MergeOperands) -> Option<Vec<u8>> {
+    let degree = existing.into_iter().chain(deltas).map(decode_degree).sum::<Option<i64>>()?;
+    Some(degree.to_le_bytes().to_vec())
+}
+
+/// Decodes a key read from `key_space`, which has to be exactly the size of its encoding.
+fn decode_key<T, const N: usize>(
+    key_space: KeySpace,
+    key: Box<[u8]>,
+    from_bytes: impl FnOnce([u8; N]) -> T,
+) -> Result<T, StorageError> {
+    match <[u8; N]>::try_from(&*key) {
+        Ok(bytes) => Ok(from_bytes(bytes)),
+        Err(_) => Err(StorageError::Decode { key_space, bytes: key }),
+    }
 }
 
 impl Storage {
-    pub fn new(storage_dir: &Path, mode: Mode) -> Self {
+    pub fn new(storage_dir: &Path, mode: Mode) -> Result<Self, StorageError> {
         if storage_dir.exists() {
-            std::fs::remove_dir_all(storage_dir).expect("could not remove data dir");
+            std::fs::remove_dir_all(storage_dir)
+                .map_err(|source| StorageError::Io { path: storage_dir.to_owned(), source })?;
         }
+        Self::open(storage_dir, mode)
+    }
+
+    /// Opens the storage at `storage_dir` without clearing it first. `mode` must match the one it was created with.
+    pub fn open(storage_dir: &Path, mode: Mode) -> Result<Self, StorageError> {
+        Self::open_with(storage_dir, mode, false)
+    }
 
+    /// Opens an existing storage for reading only. Unlike `open`, a missing directory or a database without the
+    /// column families of `mode`'s layout is an error rather than created empty.
+    pub fn open_read_only(storage_dir: &Path, mode: Mode) -> Result<Self, StorageError> {
+        Self::open_with(storage_dir, mode, true)
+    }
+
+    fn open_with(storage_dir: &Path, mode: Mode, read_only: bool) -> Result<Self, StorageError> {
         let options = {
             let mut options = Options::default();
-            options.create_if_missing(true);
-            options.create_missing_column_families(true);
+            options.create_if_missing(!read_only);
+            options.create_missing_column_families(!read_only);
             options.enable_statistics();
             options.set_max_background_jobs(4);
             options.set_max_subcompactions(4);
+            options.set_merge_operator_associative("add_degrees", add_degrees);
             options
         };
 
-        match mode {
-            Mode::SingleColumnFamily => Self::Single(SingleDB::open(&options, storage_dir)),
+        let layout = match mode {
+            Mode::SingleColumnFamily => Layout::Single(SingleDB::open(&options, storage_dir, read_only)?),
             Mode::MultipleColumnFamilies => {
-                let db = DB::open_cf(&options, storage_dir, CFS).expect("Could not create database storage");
+                let db = open_db(&options, storage_dir, CFS, read_only)?;
                 unsafe {
-                    Self::MultipleColumnFamilies {
+                    Layout::MultipleColumnFamilies {
                         thing_cf: &*(db.cf_handle(THING).unwrap() as *const _),
                         attribute_cf: &*(db.cf_handle(ATTRIBUTE).unwrap() as *const _),
                         has_forward_cf: &*(db.cf_handle(HAS_FORWARD).unwrap() as *const _),
@@ -110,95 +518,231 @@ impl Storage {
                         relates_forward_cf: &*(db.cf_handle(RELATES_FORWARD).unwrap() as *const _),
                         relates_backward_cf: &*(db.cf_handle(RELATES_BACKWARD).unwrap() as *const _),
                         relation_sibling_cf: &*(db.cf_handle(RELATION_SIBLING).unwrap() as *const _),
+                        attribute_index_cf: &*(db.cf_handle(ATTRIBUTE_INDEX).unwrap() as *const _),
+                        degree_cf: &*(db.cf_handle(DEGREE).unwrap() as *const _),
                         db,
                     }
                 }
             }
-            Mode::MultipleDatabases => Self::MultipleDatabases {
-                thing_db: SingleDB::open(&options, &storage_dir.join("thing")),
-                attribute_db: SingleDB::open(&options, &storage_dir.join("attribute")),
-                has_forward_db: SingleDB::open(&options, &storage_dir.join("has_forward")),
-                has_backward_db: SingleDB::open(&options, &storage_dir.join("has_backward")),
-                relates_forward_db: SingleDB::open(&options, &storage_dir.join("relates_forward")),
-                relates_backward_db: SingleDB::open(&options, &storage_dir.join("relates_backward")),
-                relation_sibling_db: SingleDB::open(&options, &storage_dir.join("relation_sibling")),
+            Mode::TypeColumnFamilies => {
+                // a reopened store may hold column families for types that are no longer part of the schema
+                let existing = DB::list_cf(&options, storage_dir).unwrap_or_default();
+                let cf_names = [DEFAULT_COLUMN_FAMILY_NAME.to_owned()]
+                    .into_iter()
+                    .chain(SCHEMA.iter().map(type_cf_name))
+                    .chain(existing)
+                    .unique()
+                    .collect_vec();
+                let db = open_db(&options, storage_dir, &cf_names, read_only)?;
+                unsafe {
+                    Layout::TypeColumnFamilies {
+                        type_cfs: cf_names
+                            .iter()
+                            .filter_map(|name| {
+                                Some((parse_type_cf_name(name)?, &*(db.cf_handle(name).unwrap() as *const _)))
+                            })
+                            .collect(),
+                        default_cf: &*(db.cf_handle(DEFAULT_COLUMN_FAMILY_NAME).unwrap() as *const _),
+                        db,
+                    }
+                }
+            }
+            Mode::MultipleDatabases => Layout::MultipleDatabases {
+                thing_db: SingleDB::open(&options, &storage_dir.join("thing"), read_only)?,
+                attribute_db: SingleDB::open(&options, &storage_dir.join("attribute"), read_only)?,
+                has_forward_db: SingleDB::open(&options, &storage_dir.join("has_forward"), read_only)?,
+                has_backward_db: SingleDB::open(&options, &storage_dir.join("has_backward"), read_only)?,
+                relates_forward_db: SingleDB::open(&options, &storage_dir.join("relates_forward"), read_only)?,
+                relates_backward_db: SingleDB::open(&options, &storage_dir.join("relates_backward"), read_only)?,
+                relation_sibling_db: SingleDB::open(&options, &storage_dir.join("relation_sibling"), read_only)?,
+                attribute_index_db: SingleDB::open(&options, &storage_dir.join("attribute_index"), read_only)?,
+                degree_db: SingleDB::open(&options, &storage_dir.join("degree"), read_only)?,
+                commit_lock: RwLock::new(()),
+            },
+            Mode::Sharded(shard_count) => Layout::Sharded {
+                shards: (0..shard_count)
+                    .map(|i| SingleDB::open(&options, &storage_dir.join(format!("shard_{i}")), read_only))
+                    .collect::<Result<_, _>>()?,
+                commit_lock: RwLock::new(()),
             },
+        };
+        Ok(Self {
+            layout,
+            faults: Faults::default(),
+            siblings: Siblings::default(),
+            snapshots: false,
+            commit_latencies: LatencyHistogram::default(),
+            written_keys: AtomicUsize::new(0),
+        })
+    }
+
+    /// Injects `faults` into every subsequent write, read, and sync of the databases.
+    pub fn with_faults(self, faults: Faults) -> Self {
+        Self { faults, ..self }
+    }
+
+    /// Writes sibling edges for the relations that `siblings` says, and reads the siblings of the other relations
+    /// through their relates edges. Only for fresh stores: a store must be read with the policy it was written with.
+    pub fn with_siblings(self, siblings: Siblings) -> Self {
+        Self { siblings, ..self }
+    }
+
+    /// Makes commits in DB and SHARD modes hold off snapshots, so that snapshots taken while commits are in flight are
+    /// consistent across the databases. Without snapshots, commits skip the lock.
+    pub fn with_snapshots(self, snapshots: bool) -> Self {
+        Self { snapshots, ..self }
+    }
+
+    pub fn faults(&self) -> &Faults {
+        &self.faults
+    }
+
+    pub fn commit_latencies(&self) -> &LatencyHistogram {
+        &self.commit_latencies
+    }
+
+    pub fn written_keys(&self) -> &AtomicUsize {
+        &self.written_keys
+    }
+
+    /// Takes a snapshot that all subsequent reads through it will see. In DB and SHARD modes, the snapshots of the
+    /// individual databases only agree with each other if the storage is `with_snapshots` or no commit is in flight.
+    pub fn snapshot(&self) -> ReadSnapshot<'_> {
+        match &self.layout {
+            Layout::Single(SingleDB { db, .. })
+            | Layout::MultipleColumnFamilies { db, .. }
+            | Layout::TypeColumnFamilies { db, .. } => ReadSnapshot::Single(db.snapshot()),
+            Layout::MultipleDatabases { commit_lock, .. } | Layout::Sharded { commit_lock, .. } => {
+                let _no_commits = commit_lock.write().unwrap();
+                ReadSnapshot::Multi(self.dbs().into_iter().map(DB::snapshot).collect())
+            }
         }
     }
 
+    pub fn iter_things<'s>(
+        &'s self,
+        snapshot: &'s ReadSnapshot<'_>,
+    ) -> impl Iterator<Item = Result<Thing, StorageError>> + 's {
+        self.iter_key_space(snapshot, KeySpace::Thing).map(|key| decode_key(KeySpace::Thing, key?, Thing::from_bytes))
+    }
+
     #[allow(dead_code)]
-    pub fn get_one_has(&self, owner: Thing) -> Option<Attribute> {
-        let prefix = [owner.as_bytes() as &[u8], &[EdgeType::Has as u8]].concat();
-        let (db, cf) = match self {
-            Self::Single(SingleDB { db, cf }) => (db, cf),
-            Self::MultipleColumnFamilies { db, has_forward_cf, .. } => (db, has_forward_cf),
-            Self::MultipleDatabases { has_forward_db: SingleDB { db, cf }, .. } => (db, cf),
-        };
-        exact_prefix_iterator_cf(db, cf, prefix)
-            .next()
-            .and_then(|k| <[u8; HasEdge::forward_encoding_size()]>::try_from(&*k).ok())
-            .map(HasEdge::from_bytes_forward)
-            .map(|HasEdge { attr, .. }| attr)
+    pub fn iter_attributes<'s>(
+        &'s self,
+        snapshot: &'s ReadSnapshot<'_>,
+    ) -> impl Iterator<Item = Result<Attribute, StorageError>> + 's {
+        self.iter_key_space(snapshot, KeySpace::Attribute)
+            .map(|key| decode_key(KeySpace::Attribute, key?, Attribute::from_bytes))
     }
 
-    pub fn get_one_owner(&self, attribute: &Attribute) -> Option<Thing> {
-        let prefix = [attribute.as_bytes() as &[u8], &[EdgeType::Has as u8]].concat();
-        let (db, cf) = match self {
-            Self::Single(SingleDB { db, cf }) => (db, cf),
-            Self::MultipleColumnFamilies { db, has_backward_cf, .. } => (db, has_backward_cf),
-            Self::MultipleDatabases { has_backward_db: SingleDB { db, cf }, .. } => (db, cf),
-        };
-        exact_prefix_iterator_cf(db, cf, prefix)
-            .next()
-            .and_then(|k| <[u8; HasEdge::backward_encoding_size()]>::try_from(&*k).ok())
-            .map(HasEdge::from_bytes_backward)
-            .map(|HasEdge { owner, .. }| owner)
+    pub fn iter_has<'s>(
+        &'s self,
+        snapshot: &'s ReadSnapshot<'_>,
+    ) -> impl Iterator<Item = Result<HasEdge, StorageError>> + 's {
+        self.iter_key_space(snapshot, KeySpace::HasForward)
+            .map(|key| decode_key(KeySpace::HasForward, key?, HasEdge::from_bytes_forward))
     }
 
-    #[allow(dead_code)]
-    pub fn iter_siblings(
-        &self,
-        start: Thing,
-        role_type: Type,
-        relation_type: Type,
-    ) -> impl Iterator<Item = Thing> + '_ {
-        let prefix =
-            [start.as_bytes() as &[u8], &[EdgeType::Sibling as u8], role_type.as_bytes(), relation_type.as_bytes()]
-                .concat();
-        let (db, cf) = match self {
-            Self::Single(SingleDB { db, cf }) => (db, cf),
-            Self::MultipleColumnFamilies { db, relation_sibling_cf, .. } => (db, relation_sibling_cf),
-            Self::MultipleDatabases { relation_sibling_db: SingleDB { db, cf }, .. } => (db, cf),
-        };
-        exact_prefix_iterator_cf(db, cf, prefix)
-            .filter_map(|k| <[u8; RelationSiblingEdge::encoding_size()]>::try_from(&*k).ok())
-            .map(RelationSiblingEdge::from_bytes)
-            .map(|RelationSiblingEdge { rhs_player, .. }| rhs_player)
-    }
-
-    pub fn get_random_sibling(&self, start: Thing, role_type: Type, relation_type: Type) -> Option<Thing> {
-        let random_relation = Thing { type_: relation_type, thing_id: ThingID { id: thread_rng().gen() } };
-        let prefix = [start.as_bytes() as &[u8], role_type.as_bytes(), random_relation.as_bytes()].concat();
-        let start = [&prefix as &[u8], &thread_rng().gen::<usize>().to_be_bytes()].concat();
-        let (db, cf) = match self {
-            Self::Single(SingleDB { db, cf }) => (db, cf),
-            Self::MultipleColumnFamilies { db, relation_sibling_cf, .. } => (db, relation_sibling_cf),
-            Self::MultipleDatabases { relation_sibling_db: SingleDB { db, cf }, .. } => (db, cf),
-        };
-        exact_prefix_iterator_cf_from(db, cf, prefix, &start)
-            .next()
-            .and_then(|k| <[u8; RelationSiblingEdge::encoding_size()]>::try_from(&*k).ok())
-            .map(RelationSiblingEdge::from_bytes)
-            .map(|RelationSiblingEdge { rhs_player, .. }| rhs_player)
+    pub fn iter_relates<'s>(
+        &'s self,
+        snapshot: &'s ReadSnapshot<'_>,
+    ) -> impl Iterator<Item = Result<RelatesEdge, StorageError>> + 's {
+        self.iter_key_space(snapshot, KeySpace::RelatesForward)
+            .map(|key| decode_key(KeySpace::RelatesForward, key?, RelatesEdge::from_bytes_forward))
     }
 
-    pub fn commit(&self, writer: WriteHandle) {
-        match self {
-            Self::Single(SingleDB { db, .. }) | Self::MultipleColumnFamilies { db, .. } => {
-                let WriteHandle::Single { batch, .. } = writer else { unreachable!() };
-                db.write_without_wal(batch).unwrap()
+    pub fn iter_degree_keys<'s>(
+        &'s self,
+        snapshot: &'s ReadSnapshot<'_>,
+    ) -> impl Iterator<Item = Result<DegreeKey, StorageError>> + 's {
+        self.iter_key_space(snapshot, KeySpace::Degree)
+            .map(|key| decode_key(KeySpace::Degree, key?, DegreeKey::from_bytes))
+    }
+
+    fn iter_key_space<'s>(
+        &'s self,
+        snapshot: &'s ReadSnapshot<'_>,
+        key_space: KeySpace,
+    ) -> impl Iterator<Item = Result<Box<[u8]>, StorageError>> + 's {
+        let shared =
+            matches!(self.layout, Layout::Single(_) | Layout::TypeColumnFamilies { .. } | Layout::Sharded { .. });
+        self.locate_all(key_space)
+            .into_iter()
+            .flat_map(move |(db_index, db, cf)| {
+                self.checked(db.iterator_cf_opt(cf, snapshot.read_options(db_index), IteratorMode::Start))
+            })
+            .filter(move |key| key.as_ref().map_or(true, |key| !shared || KeySpace::of(key) == Some(key_space)))
+    }
+
+    /// Iterates over the keys starting with `prefix` in one column family, from `start` onwards.
+    fn prefix_iterator_in<'s>(
+        &'s self,
+        (db_index, db, cf): (usize, &'s DB, &'s ColumnFamily),
+        snapshot: &'s ReadSnapshot<'_>,
+        prefix: &[u8],
+        start: &[u8],
+    ) -> impl Iterator<Item = Result<Box<[u8]>, StorageError>> + 's {
+        let mut read_options = snapshot.read_options(db_index);
+        read_options.set_prefix_same_as_start(true);
+        let prefix = prefix.to_vec();
+        self.checked(db.iterator_cf_opt(cf, read_options, IteratorMode::From(start, Direction::Forward)))
+            .take_while(move |key| key.as_ref().map_or(true, |key| key.starts_with(&prefix)))
+    }
+
+    /// The keys of a database iterator, with its errors. An injected read fault takes the place of the first key.
+    fn checked<'s>(
+        &'s self,
+        iterator: impl Iterator<Item = Result<(Box<[u8]>, Box<[u8]>), speedb::Error>> + 's,
+    ) -> impl Iterator<Item = Result<Box<[u8]>, StorageError>> + 's {
+        let mut fault = self.faults.check(Point::Read).err();
+        let iterator = iterator.map(|entry| entry.map(|(key, _)| key).map_err(StorageError::Iterate));
+        iterator.map(move |key| match fault.take() {
+            Some(fault) => Err(StorageError::Injected(fault)),
+            None => key,
+        })
+    }
+
+    /// All databases backing the storage. Their position in this list is the `db_index` used by `locate`.
+    fn dbs(&self) -> Vec<&DB> {
+        match &self.layout {
+            Layout::Single(SingleDB { db, .. })
+            | Layout::MultipleColumnFamilies { db, .. }
+            | Layout::TypeColumnFamilies { db, .. } => vec![db],
+            Layout::MultipleDatabases { .. } => {
+                KeySpace::ALL.into_iter().map(|key_space| self.locate(key_space, &[]).1).collect()
             }
-            Self::MultipleDatabases {
+            Layout::Sharded { shards, .. } => shards.iter().map(|SingleDB { db, .. }| db).collect(),
+        }
+    }
+
+    /// Finds the database, and its index in `dbs`, and column family that hold `key` from `key_space`.
+    fn locate(&self, key_space: KeySpace, key: &[u8]) -> (usize, &DB, &ColumnFamily) {
+        match &self.layout {
+            Layout::Single(SingleDB { db, cf }) => (0, db, cf),
+            Layout::MultipleColumnFamilies {
+                db,
+                thing_cf,
+                attribute_cf,
+                has_forward_cf,
+                has_backward_cf,
+                relates_forward_cf,
+                relates_backward_cf,
+                relation_sibling_cf,
+                attribute_index_cf,
+                degree_cf,
+            } => match key_space {
+                KeySpace::Thing => (0, db, thing_cf),
+                KeySpace::Attribute => (0, db, attribute_cf),
+                KeySpace::HasForward => (0, db, has_forward_cf),
+                KeySpace::HasBackward => (0, db, has_backward_cf),
+                KeySpace::RelatesForward => (0, db, relates_forward_cf),
+                KeySpace::RelatesBackward => (0, db, relates_backward_cf),
+                KeySpace::RelationSibling => (0, db, relation_sibling_cf),
+                KeySpace::AttributeIndex => (0, db, attribute_index_cf),
+                KeySpace::Degree => (0, db, degree_cf),
+            },
+            Layout::TypeColumnFamilies { db, type_cfs, default_cf } => (0, db, type_cf(type_cfs, default_cf, key)),
+            Layout::MultipleDatabases {
                 thing_db,
                 attribute_db,
                 has_forward_db,
@@ -206,238 +750,673 @@ impl Storage {
                 relates_forward_db,
                 relates_backward_db,
                 relation_sibling_db,
+                attribute_index_db,
+                degree_db,
+                ..
             } => {
-                let WriteHandle::Multi {
-                    thing_batch,
-                    attribute_batch,
-                    has_forward_batch,
-                    has_backward_batch,
-                    relates_forward_batch,
-                    relates_backward_batch,
-                    relation_sibling_batch,
-                    ..
-                } = writer
-                else {
-                    unreachable!()
+                let SingleDB { db, cf } = match key_space {
+                    KeySpace::Thing => thing_db,
+                    KeySpace::Attribute => attribute_db,
+                    KeySpace::HasForward => has_forward_db,
+                    KeySpace::HasBackward => has_backward_db,
+                    KeySpace::RelatesForward => relates_forward_db,
+                    KeySpace::RelatesBackward => relates_backward_db,
+                    KeySpace::RelationSibling => relation_sibling_db,
+                    KeySpace::AttributeIndex => attribute_index_db,
+                    KeySpace::Degree => degree_db,
                 };
-                thing_db.db.write_without_wal(thing_batch).unwrap();
-                attribute_db.db.write_without_wal(attribute_batch).unwrap();
-                has_forward_db.db.write_without_wal(has_forward_batch).unwrap();
-                has_backward_db.db.write_without_wal(has_backward_batch).unwrap();
-                relates_forward_db.db.write_without_wal(relates_forward_batch).unwrap();
-                relates_backward_db.db.write_without_wal(relates_backward_batch).unwrap();
-                relation_sibling_db.db.write_without_wal(relation_sibling_batch).unwrap();
+                (key_space as usize, db, cf)
+            }
+            Layout::Sharded { shards, .. } => {
+                let index = shard_index(key, shards.len());
+                (index, &shards[index].db, shards[index].cf)
             }
         }
     }
 
-    pub fn print_stats(&self) {
-        let total = match self {
-            Self::Single(SingleDB { db, cf }) => db.iterator_cf(cf, IteratorMode::Start).count(),
-            Self::MultipleColumnFamilies { db, .. } => CFS
-                .iter()
-                .map(|cf| db.iterator_cf(db.cf_handle(cf).unwrap(), IteratorMode::Start).count())
-                .sum::<usize>(),
-            Self::MultipleDatabases {
-                thing_db,
-                attribute_db,
-                has_forward_db,
-                has_backward_db,
-                relates_forward_db,
-                relates_backward_db,
-                relation_sibling_db,
-            } => [
-                thing_db,
-                attribute_db,
-                has_forward_db,
-                has_backward_db,
-                relates_forward_db,
-                relates_backward_db,
-                relation_sibling_db,
-            ]
-            .into_iter()
-            .map(|SingleDB { db, cf }| db.iterator_cf(cf, IteratorMode::Start).count())
-            .sum(),
+    /// Every database and column family that may hold keys from `key_space`.
+    fn locate_all(&self, key_space: KeySpace) -> Vec<(usize, &DB, &ColumnFamily)> {
+        match &self.layout {
+            Layout::Sharded { shards, .. } => {
+                shards.iter().enumerate().map(|(index, SingleDB { db, cf })| (index, db, *cf)).collect()
+            }
+            Layout::TypeColumnFamilies { db, type_cfs, default_cf } => {
+                type_cfs.values().chain([default_cf]).map(|cf| (0, db, *cf)).collect()
+            }
+            _ => vec![self.locate(key_space, &[])],
+        }
+    }
+
+    pub fn commit(&self, writer: WriteHandle) -> Result<(), StorageError> {
+        let _commit = match &self.layout {
+            Layout::MultipleDatabases { commit_lock, .. } | Layout::Sharded { commit_lock, .. } if self.snapshots => {
+                Some(commit_lock.read().unwrap())
+            }
+            _ => None,
         };
-        println!("Total keys in DB: {total}")
+        let start = Instant::now();
+        let mut write_options = WriteOptions::default();
+        write_options.disable_wal(writer.durability == Durability::None);
+        write_options.set_sync(writer.durability == Durability::Sync);
+        let keys = writer.batches.iter().map(WriteBatch::len).sum::<usize>();
+        // with several databases, a failure partway leaves the batches before it committed
+        for (db, batch) in self.dbs().into_iter().zip(writer.batches) {
+            if !batch.is_empty() {
+                self.faults.check(Point::Write).map_err(StorageError::Injected)?;
+                if writer.durability == Durability::Sync {
+                    self.faults.check(Point::Sync).map_err(StorageError::Injected)?;
+                }
+                db.write_opt(batch, &write_options).map_err(StorageError::Write)?;
+            }
+        }
+        self.commit_latencies.record(start.elapsed());
+        self.written_keys.fetch_add(keys, Ordering::Relaxed);
+        Ok(())
     }
 
-    pub fn writer(&self) -> WriteHandle<'_> {
-        match self {
-            Self::Single(_) | Self::MultipleColumnFamilies { .. } => {
-                WriteHandle::Single { batch: WriteBatch::default(), storage: self }
+    pub fn print_stats(&self) -> Result<(), StorageError> {
+        let mut key_spaces = BTreeMap::<Option<KeySpace>, SpaceUsage>::new();
+        for (_, db, cf) in self.column_families() {
+            let mut in_cf = BTreeMap::<Option<KeySpace>, SpaceUsage>::new();
+            for entry in db.iterator_cf(cf, IteratorMode::Start) {
+                let (key, value) = entry.map_err(StorageError::Iterate)?;
+                let usage = in_cf.entry(KeySpace::of(&key)).or_default();
+                usage.keys += 1;
+                usage.logical_bytes += (key.len() + value.len()) as u64;
             }
-            Self::MultipleDatabases { .. } => WriteHandle::Multi {
-                thing_batch: WriteBatch::default(),
-                attribute_batch: WriteBatch::default(),
-                has_forward_batch: WriteBatch::default(),
-                has_backward_batch: WriteBatch::default(),
-                relates_forward_batch: WriteBatch::default(),
-                relates_backward_batch: WriteBatch::default(),
-                relation_sibling_batch: WriteBatch::default(),
-                storage: self,
-            },
+            // SSTs hold a mix of the key spaces sharing the column family, so theirs is only a share by logical size
+            let sst_bytes =
+                db.property_int_value_cf(cf, "rocksdb.live-sst-files-size").map_err(StorageError::Read)?.unwrap_or(0);
+            let logical_bytes = in_cf.values().map(|usage| usage.logical_bytes).sum::<u64>();
+            for (key_space, usage) in in_cf {
+                let total = key_spaces.entry(key_space).or_default();
+                total.keys += usage.keys;
+                total.logical_bytes += usage.logical_bytes;
+                total.sst_bytes += (sst_bytes as u128 * usage.logical_bytes as u128 / logical_bytes as u128) as u64;
+            }
+        }
+        let total = key_spaces.values().fold(SpaceUsage::default(), |total, usage| SpaceUsage {
+            keys: total.keys + usage.keys,
+            logical_bytes: total.logical_bytes + usage.logical_bytes,
+            sst_bytes: total.sst_bytes + usage.sst_bytes,
+        });
+        println!("Total keys in DB: {}", total.keys);
+        println!("Pending compaction bytes: {}", self.pending_compaction_bytes()?);
+
+        let dbs = self.dbs();
+        let disk_usage = dbs
+            .iter()
+            .map(|db| {
+                DiskUsage::of(db.path()).map_err(|source| StorageError::Io { path: db.path().to_owned(), source })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if dbs.len() > 1 {
+            for (db, usage) in dbs.iter().zip(&disk_usage) {
+                println!("Disk usage of {}: {usage}", db.path().display());
+            }
+        }
+        let disk_usage = disk_usage.into_iter().sum::<DiskUsage>();
+        println!("Disk usage: {disk_usage}");
+        if let Some(amplification) = disk::space_amplification(disk_usage.total(), total.logical_bytes) {
+            println!(
+                "Logical bytes: {}, space amplification: {amplification:.2} ({:.2} in SSTs)",
+                Bytes(total.logical_bytes),
+                disk::space_amplification(disk_usage.sst, total.logical_bytes).unwrap(),
+            );
+        }
+
+        println!("By key space (SST sizes of shared column families split by logical size):");
+        for (key_space, usage) in &key_spaces {
+            let key_space = key_space.map_or("unknown".to_owned(), |key_space| format!("{key_space:?}"));
+            println!(
+                "  {key_space:<16} {:>12} keys {:>12} logical {:>12} in SSTs ({:.1}% of logical bytes)",
+                usage.keys,
+                Bytes(usage.logical_bytes).to_string(),
+                Bytes(usage.sst_bytes).to_string(),
+                usage.logical_bytes as f64 * 100.0 / total.logical_bytes.max(1) as f64,
+            );
+        }
+        // backward edges duplicate forward ones, and sibling edges are derived from relations, all to speed up reads
+        let share = |spaces: &[KeySpace]| {
+            let usages = spaces.iter().filter_map(|&key_space| key_spaces.get(&Some(key_space)));
+            let (logical_bytes, sst_bytes) =
+                usages.fold((0, 0), |(logical, sst), usage| (logical + usage.logical_bytes, sst + usage.sst_bytes));
+            format!(
+                "{} logical ({:.1}%), {} in SSTs ({:.1}%)",
+                Bytes(logical_bytes),
+                logical_bytes as f64 * 100.0 / total.logical_bytes.max(1) as f64,
+                Bytes(sst_bytes),
+                sst_bytes as f64 * 100.0 / total.sst_bytes.max(1) as f64,
+            )
+        };
+        println!("Backward edges: {}", share(&[KeySpace::HasBackward, KeySpace::RelatesBackward]));
+        println!("Sibling edges: {}", share(&[KeySpace::RelationSibling]));
+        Ok(())
+    }
+
+    /// The compaction debt of the storage: how many bytes compaction estimates it has to rewrite to settle the LSM
+    /// trees down.
+    pub fn pending_compaction_bytes(&self) -> Result<u64, StorageError> {
+        self.property_values("rocksdb.estimate-pending-compaction-bytes").sum()
+    }
+
+    /// Whether any of the databases is slowing down or blocking writes, and the worst of them if so.
+    pub fn write_stall(&self) -> Result<Stall, StorageError> {
+        let property = |db: &DB, name| Ok(db.property_int_value(name).map_err(StorageError::Read)?.unwrap_or(0));
+        let stalls = self.dbs().into_iter().map(|db| {
+            Ok(match (property(db, "rocksdb.is-write-stopped")?, property(db, "rocksdb.actual-delayed-write-rate")?) {
+                (0, 0) => Stall::None,
+                (0, _) => Stall::Delayed,
+                _ => Stall::Stopped,
+            })
+        });
+        Ok(stalls.process_results(|stalls| stalls.max())?.unwrap_or_default())
+    }
+
+    /// Memory held by the stores: memtables, the block cache, and the index and filter blocks of open SSTs.
+    pub fn memory_usage(&self) -> Result<Vec<(&'static str, u64)>, StorageError> {
+        Ok(vec![
+            ("memtables", self.property_values("rocksdb.cur-size-all-mem-tables").sum::<Result<_, _>>()?),
+            // every column family is opened from clones of the same options, which share one block cache
+            (
+                "block cache",
+                self.property_values("rocksdb.block-cache-usage").process_results(|values| values.max())?.unwrap_or(0),
+            ),
+            ("table readers", self.property_values("rocksdb.estimate-table-readers-mem").sum::<Result<_, _>>()?),
+        ])
+    }
+
+    /// An integer property of every column family in use.
+    fn property_values<'a>(&'a self, property: &'a str) -> impl Iterator<Item = Result<u64, StorageError>> + 'a {
+        self.column_families().map(move |(_, db, cf)| {
+            Ok(db.property_int_value_cf(cf, property).map_err(StorageError::Read)?.unwrap_or(0))
+        })
+    }
+
+    /// Every column family in use, once each, with its database and that database's index in `dbs`.
+    fn column_families(&self) -> impl Iterator<Item = (usize, &DB, &ColumnFamily)> {
+        KeySpace::ALL
+            .into_iter()
+            .flat_map(|key_space| self.locate_all(key_space))
+            .unique_by(|&(_, _, cf)| cf as *const ColumnFamily)
+    }
+
+    pub fn writer(&self) -> WriteHandle<'_> {
+        WriteHandle {
+            batches: self.dbs().iter().map(|_| WriteBatch::default()).collect(),
+            storage: self,
+            degrees: true,
+            durability: Durability::None,
+            recorded: None,
+            written: [(0, 0); KeySpace::ALL.len()],
+            index: None,
+            attribute_index: false,
         }
     }
 }
 
-pub enum WriteHandle<'a> {
-    Single {
-        batch: WriteBatch,
-        storage: &'a Storage,
-    },
-    Multi {
-        thing_batch: WriteBatch,
-        attribute_batch: WriteBatch,
-        has_forward_batch: WriteBatch,
-        has_backward_batch: WriteBatch,
-        relates_forward_batch: WriteBatch,
-        relates_backward_batch: WriteBatch,
-        relation_sibling_batch: WriteBatch,
-        storage: &'a Storage,
-    },
+/// Reads of the storage. Through `Storage` they see what has been committed; through an `indexed` `WriteHandle`, they
+/// see what it has buffered on top of that.
+pub trait Reads {
+    fn siblings(&self) -> Siblings;
+
+    /// Iterates over the keys of `key_space` starting with `prefix`, from `start` onwards, in the column family that
+    /// holds `location`.
+    fn scan<'s>(
+        &'s self,
+        snapshot: &'s ReadSnapshot<'_>,
+        key_space: KeySpace,
+        location: &[u8],
+        prefix: &[u8],
+        start: &[u8],
+    ) -> impl Iterator<Item = Result<Box<[u8]>, StorageError>> + use<'s, Self>;
+
+    /// The value of `key` in `key_space`, if it is present.
+    fn get(
+        &self,
+        snapshot: &ReadSnapshot<'_>,
+        key_space: KeySpace,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, StorageError>;
+
+    fn get_one_has(&self, snapshot: &ReadSnapshot<'_>, owner: Thing) -> Result<Option<Attribute>, StorageError> {
+        let prefix = [owner.as_bytes() as &[u8], &[EdgeType::Has as u8]].concat();
+        self.exact_prefix_iterator(snapshot, KeySpace::HasForward, &prefix, &prefix)
+            .next()
+            .map(|key| decode_key(KeySpace::HasForward, key?, HasEdge::from_bytes_forward))
+            .transpose()
+            .map(|edge| edge.map(|HasEdge { attr, .. }| attr))
+    }
+
+    fn get_one_owner(&self, snapshot: &ReadSnapshot<'_>, attribute: &Attribute) -> Result<Option<Thing>, StorageError> {
+        let prefix = [attribute.as_bytes() as &[u8], &[EdgeType::Has as u8]].concat();
+        self.exact_prefix_iterator(snapshot, KeySpace::HasBackward, &prefix, &prefix)
+            .next()
+            .map(|key| decode_key(KeySpace::HasBackward, key?, HasEdge::from_bytes_backward))
+            .transpose()
+            .map(|edge| edge.map(|HasEdge { owner, .. }| owner))
+    }
+
+    /// Streams the players of every relation of `relation_type` that `start` plays `role_type` in, other than `start`.
+    /// Siblings in relations without sibling edges come after the others, through a traversal of their relates edges.
+    fn iter_siblings<'s>(
+        &'s self,
+        snapshot: &'s ReadSnapshot<'_>,
+        start: Thing,
+        role_type: Type,
+        relation_type: Type,
+    ) -> impl Iterator<Item = Result<Thing, StorageError>> + 's {
+        let prefix =
+            [start.as_bytes() as &[u8], &[EdgeType::Sibling as u8], role_type.as_bytes(), relation_type.as_bytes()]
+                .concat();
+        let siblings = self.siblings();
+        let materialised = (siblings != Siblings::Never).then(|| {
+            self.exact_prefix_iterator(snapshot, KeySpace::RelationSibling, &prefix, &prefix)
+                .map(|key| decode_key(KeySpace::RelationSibling, key?, RelationSiblingEdge::from_bytes))
+                .map(|edge| edge.map(|RelationSiblingEdge { rhs_player, .. }| rhs_player))
+        });
+        let traversed = (siblings != Siblings::Always).then(|| {
+            let relations = self.exact_relations_of(snapshot, start, role_type, relation_type);
+            relations
+                .map(move |rel| {
+                    let players = self.players(snapshot, rel?)?;
+                    Ok(if siblings.materialised(players.len()) { Vec::new() } else { players })
+                })
+                .flatten_ok()
+                .filter(move |player| player.as_ref().map_or(true, |player| *player != start))
+        });
+        materialised.into_iter().flatten().chain(traversed.into_iter().flatten())
+    }
+
+    /// The relations of `relation_type` that `player` plays `role_type` in.
+    fn exact_relations_of<'s>(
+        &'s self,
+        snapshot: &'s ReadSnapshot<'_>,
+        player: Thing,
+        role_type: Type,
+        relation_type: Type,
+    ) -> impl Iterator<Item = Result<Thing, StorageError>> + 's {
+        let prefix =
+            [player.as_bytes() as &[u8], &[EdgeType::Relates as u8], role_type.as_bytes(), relation_type.as_bytes()]
+                .concat();
+        self.exact_prefix_iterator(snapshot, KeySpace::RelatesBackward, &prefix, &prefix)
+            .map(|key| decode_key(KeySpace::RelatesBackward, key?, RelatesEdge::from_bytes_backward))
+            .map(|edge| edge.map(|RelatesEdge { rel, .. }| rel))
+    }
+
+    /// Every player of `rel`, in any role.
+    fn players(&self, snapshot: &ReadSnapshot<'_>, rel: Thing) -> Result<Vec<Thing>, StorageError> {
+        let prefix = [rel.as_bytes() as &[u8], &[EdgeType::Relates as u8]].concat();
+        self.exact_prefix_iterator(snapshot, KeySpace::RelatesForward, &prefix, &prefix)
+            .map(|key| decode_key(KeySpace::RelatesForward, key?, RelatesEdge::from_bytes_forward))
+            .map_ok(|RelatesEdge { player, .. }| player)
+            .collect()
+    }
+
+    /// Seeks to a random relation among `start`'s siblings, wrapping around to the first one if the seek overshoots.
+    /// Unless every relation has sibling edges, the seek goes through the relates edges instead, and a random other
+    /// player of the relation it lands on is read.
+    fn get_random_sibling(
+        &self,
+        snapshot: &ReadSnapshot<'_>,
+        start: Thing,
+        role_type: Type,
+        relation_type: Type,
+    ) -> Result<Option<Thing>, StorageError> {
+        let (key_space, edge_type) = match self.siblings() {
+            Siblings::Always => (KeySpace::RelationSibling, EdgeType::Sibling),
+            _ => (KeySpace::RelatesBackward, EdgeType::Relates),
+        };
+        let prefix =
+            [start.as_bytes() as &[u8], &[edge_type as u8], role_type.as_bytes(), relation_type.as_bytes()].concat();
+        let random_relation_id: [u8; size_of::<ThingID>()] = thread_rng().gen();
+        let seek = [&prefix as &[u8], &random_relation_id].concat();
+        let Some(key) = self
+            .exact_prefix_iterator(snapshot, key_space, &prefix, &seek)
+            .next()
+            .or_else(|| self.exact_prefix_iterator(snapshot, key_space, &prefix, &prefix).next())
+            .transpose()?
+        else {
+            return Ok(None);
+        };
+        if self.siblings() == Siblings::Always {
+            let RelationSiblingEdge { rhs_player, .. } = decode_key(key_space, key, RelationSiblingEdge::from_bytes)?;
+            return Ok(Some(rhs_player));
+        }
+        let RelatesEdge { rel, .. } = decode_key(key_space, key, RelatesEdge::from_bytes_backward)?;
+        let players = self.players(snapshot, rel)?;
+        Ok(players.into_iter().filter(|&player| player != start).collect_vec().choose(&mut thread_rng()).copied())
+    }
+
+    /// Seeks to a random thing of `type_`, wrapping around to the first one if the seek overshoots. In SHARD mode, the
+    /// seek lands in a random shard.
+    fn get_random_thing(&self, snapshot: &ReadSnapshot<'_>, type_: Type) -> Result<Option<Thing>, StorageError> {
+        let prefix = type_.as_bytes();
+        let seek = Thing { type_, thing_id: ThingID { id: thread_rng().gen() } };
+        self.scan(snapshot, KeySpace::Thing, seek.as_bytes(), prefix, seek.as_bytes())
+            .next()
+            .or_else(|| self.scan(snapshot, KeySpace::Thing, seek.as_bytes(), prefix, prefix).next())
+            .map(|key| decode_key(KeySpace::Thing, key?, Thing::from_bytes))
+            .transpose()
+    }
+
+    /// Streams the owners of attributes of `attr_type` whose value falls in `range`, in value order.
+    fn owners_in_range<'s>(
+        &'s self,
+        snapshot: &'s ReadSnapshot<'_>,
+        attr_type: AttributeType,
+        range: Range<u64>,
+    ) -> impl Iterator<Item = Result<Thing, StorageError>> + 's {
+        let prefix = AttributeIndexEntry::type_prefix(attr_type);
+        let start = AttributeIndexEntry::value_prefix(attr_type, range.start);
+        self.exact_prefix_iterator(snapshot, KeySpace::AttributeIndex, &prefix, &start)
+            .map(|key| decode_key(KeySpace::AttributeIndex, key?, AttributeIndexEntry::from_bytes))
+            .take_while(move |entry| {
+                entry.as_ref().map_or(true, |AttributeIndexEntry { attr, .. }| attr.value < range.end)
+            })
+            .map(|entry| entry.map(|AttributeIndexEntry { owner, .. }| owner))
+    }
+
+    /// Whether `key` is present in `key_space`, as of the latest commit.
+    fn contains(&self, key_space: KeySpace, key: &[u8]) -> Result<bool, StorageError> {
+        Ok(self.get(&ReadSnapshot::Latest, key_space, key)?.is_some())
+    }
+
+    /// Reads the degree counter of `player` in relations of `rel_type` as `role_type`.
+    fn degree(
+        &self,
+        snapshot: &ReadSnapshot<'_>,
+        player: Thing,
+        role_type: Type,
+        rel_type: Type,
+    ) -> Result<i64, StorageError> {
+        let key = DegreeKey { player, role_type, rel_type }.to_bytes();
+        match self.get(snapshot, KeySpace::Degree, &key)? {
+            Some(value) => decode_degree(&value)
+                .ok_or_else(|| StorageError::Decode { key_space: KeySpace::Degree, bytes: value.into() }),
+            None => Ok(0),
+        }
+    }
+
+    /// Iterates over the keys of `key_space` starting with `prefix`, from `start` onwards.
+    fn exact_prefix_iterator<'s>(
+        &'s self,
+        snapshot: &'s ReadSnapshot<'_>,
+        key_space: KeySpace,
+        prefix: &[u8],
+        start: &[u8],
+    ) -> impl Iterator<Item = Result<Box<[u8]>, StorageError>> + use<'s, Self> {
+        self.scan(snapshot, key_space, prefix, prefix, start)
+    }
+}
+
+impl Reads for Storage {
+    fn siblings(&self) -> Siblings {
+        self.siblings
+    }
+
+    fn scan<'s>(
+        &'s self,
+        snapshot: &'s ReadSnapshot<'_>,
+        key_space: KeySpace,
+        location: &[u8],
+        prefix: &[u8],
+        start: &[u8],
+    ) -> impl Iterator<Item = Result<Box<[u8]>, StorageError>> + use<'s> {
+        self.prefix_iterator_in(self.locate(key_space, location), snapshot, prefix, start)
+    }
+
+    fn get(
+        &self,
+        snapshot: &ReadSnapshot<'_>,
+        key_space: KeySpace,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        let (db_index, db, cf) = self.locate(key_space, key);
+        self.faults.check(Point::Read).map_err(StorageError::Injected)?;
+        db.get_cf_opt(cf, key, &snapshot.read_options(db_index)).map_err(StorageError::Read)
+    }
+}
+
+/// Buffers writes until they are committed, with one batch per database in the order of `Storage::dbs`.
+pub struct WriteHandle<'a> {
+    batches: Vec<WriteBatch>,
+    storage: &'a Storage,
+    degrees: bool,
+    durability: Durability,
+    /// Every key put so far, if recording.
+    recorded: Option<Vec<(KeySpace, Vec<u8>)>>,
+    /// How many keys have been put into each key space so far, and how many bytes they take up.
+    written: [(u64, u64); KeySpace::ALL.len()],
+    /// What has been buffered for each key so far, if indexed.
+    index: Option<BTreeMap<(KeySpace, Vec<u8>), Buffered>>,
+    attribute_index: bool,
+}
+
+/// What an indexed `WriteHandle` has buffered for a key.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Buffered {
+    Put,
+    Delete,
+    /// The sum of the deltas merged into a degree counter.
+    Merge(i64),
 }
 
 impl WriteHandle<'_> {
+    /// Stops `put_relation` and `delete_relation` from maintaining degree counters, to measure what they cost.
+    pub fn without_degrees(self) -> Self {
+        Self { degrees: false, ..self }
+    }
+
+    pub fn with_durability(self, durability: Durability) -> Self {
+        Self { durability, ..self }
+    }
+
+    /// Makes `put_ownership` also write an attribute index entry, which range queries read.
+    pub fn with_attribute_index(self) -> Self {
+        Self { attribute_index: true, ..self }
+    }
+
+    pub fn attribute_index(&self) -> bool {
+        self.attribute_index
+    }
+
+    /// Keeps a list of the keys put through the handle, for checking afterwards what made it into the storage.
+    pub fn recording(self) -> Self {
+        Self { recorded: Some(Vec::new()), ..self }
+    }
+
+    /// Indexes the writes as they are buffered, so that reads through the handle see them on top of the storage, the
+    /// way reads inside a transaction do. Every write then also inserts into the index, and every read merges with it.
+    pub fn indexed(self) -> Self {
+        Self { index: Some(BTreeMap::new()), ..self }
+    }
+
+    /// The keys put so far, if recording. Merges into degree counters are not included.
+    pub fn recorded(&self) -> Option<&[(KeySpace, Vec<u8>)]> {
+        self.recorded.as_deref()
+    }
+
+    /// How many keys have been put into `key_space` through the handle, and how many bytes they take up.
+    pub fn written(&self, key_space: KeySpace) -> (u64, u64) {
+        self.written[key_space as usize]
+    }
+
     pub fn put_entity(&mut self, entity: Thing) {
-        self.thing_batch_put(entity.as_bytes());
+        self.put(KeySpace::Thing, entity.as_bytes());
     }
 
     pub fn put_attribute(&mut self, attribute: Attribute) {
-        self.attribute_batch_put(attribute.as_bytes());
+        self.put(KeySpace::Attribute, attribute.as_bytes());
     }
 
     pub fn put_ownership(&mut self, owner: Thing, attribute: Attribute) {
         let has_edge = HasEdge { owner, attr: attribute };
-        self.has_forward_batch_put(&has_edge.to_forward_bytes());
-        self.has_backward_batch_put(&has_edge.to_backward_bytes());
+        self.put(KeySpace::HasForward, &has_edge.to_forward_bytes());
+        self.put(KeySpace::HasBackward, &has_edge.to_backward_bytes());
+        if self.attribute_index {
+            self.put(KeySpace::AttributeIndex, &AttributeIndexEntry { attr: attribute, owner }.to_bytes());
+        }
     }
 
+    /// Writes `rel` with its players, and with sibling edges between them if the storage's `Siblings` say so.
     pub fn put_relation(&mut self, rel: Thing, players: impl IntoIterator<Item = (Type, Thing)>) {
-        self.thing_batch_put(rel.as_bytes());
+        self.put(KeySpace::Thing, rel.as_bytes());
 
         let players = players.into_iter().collect_vec();
 
         for &(role_type, player) in &players {
             let relates_edge = RelatesEdge { rel, role_type, player };
-            self.relates_forward_batch_put(&relates_edge.to_forward_bytes());
-            self.relates_backward_batch_put(&relates_edge.to_backward_bytes());
+            self.put(KeySpace::RelatesForward, &relates_edge.to_forward_bytes());
+            self.put(KeySpace::RelatesBackward, &relates_edge.to_backward_bytes());
+            if self.degrees {
+                self.merge_degree(DegreeKey { player, role_type, rel_type: rel.type_ }, 1);
+            }
         }
 
+        if !self.storage.siblings.materialised(players.len()) {
+            return;
+        }
         for ((lhs_role_type, lhs_player), (rhs_role_type, rhs_player)) in players.into_iter().tuple_combinations() {
             let shortcut_edge = RelationSiblingEdge { lhs_player, lhs_role_type, rel, rhs_role_type, rhs_player };
-            self.relation_sibling_batch_put(&shortcut_edge.to_forward_bytes());
-            self.relation_sibling_batch_put(&shortcut_edge.to_backward_bytes());
+            self.put(KeySpace::RelationSibling, &shortcut_edge.to_forward_bytes());
+            self.put(KeySpace::RelationSibling, &shortcut_edge.to_backward_bytes());
         }
     }
 
-    fn thing_batch_put(&mut self, key: &[u8]) {
-        match self {
-            WriteHandle::Single {
-                batch,
-                storage: Storage::Single(SingleDB { cf, .. }) | Storage::MultipleColumnFamilies { thing_cf: cf, .. },
-            } => batch.put_cf(cf, key, []),
-            WriteHandle::Multi {
-                thing_batch,
-                storage: Storage::MultipleDatabases { thing_db: SingleDB { cf, .. }, .. },
-                ..
-            } => thing_batch.put_cf(cf, key, []),
-            _ => unreachable!(),
+    /// Deletes everything `put_relation` wrote for `rel`. `players` must be the same as the ones it was put with.
+    #[allow(dead_code)]
+    pub fn delete_relation(&mut self, rel: Thing, players: impl IntoIterator<Item = (Type, Thing)>) {
+        self.delete(KeySpace::Thing, rel.as_bytes());
+
+        let players = players.into_iter().collect_vec();
+
+        for &(role_type, player) in &players {
+            let relates_edge = RelatesEdge { rel, role_type, player };
+            self.delete(KeySpace::RelatesForward, &relates_edge.to_forward_bytes());
+            self.delete(KeySpace::RelatesBackward, &relates_edge.to_backward_bytes());
+            if self.degrees {
+                self.merge_degree(DegreeKey { player, role_type, rel_type: rel.type_ }, -1);
+            }
         }
-    }
 
-    fn attribute_batch_put(&mut self, key: &[u8]) {
-        match self {
-            WriteHandle::Single {
-                batch,
-                storage: Storage::Single(SingleDB { cf, .. }) | Storage::MultipleColumnFamilies { attribute_cf: cf, .. },
-            } => batch.put_cf(cf, key, []),
-            WriteHandle::Multi {
-                attribute_batch,
-                storage: Storage::MultipleDatabases { attribute_db: SingleDB { cf, .. }, .. },
-                ..
-            } => attribute_batch.put_cf(cf, key, []),
-            _ => unreachable!(),
+        if !self.storage.siblings.materialised(players.len()) {
+            return;
+        }
+        for ((lhs_role_type, lhs_player), (rhs_role_type, rhs_player)) in players.into_iter().tuple_combinations() {
+            let shortcut_edge = RelationSiblingEdge { lhs_player, lhs_role_type, rel, rhs_role_type, rhs_player };
+            self.delete(KeySpace::RelationSibling, &shortcut_edge.to_forward_bytes());
+            self.delete(KeySpace::RelationSibling, &shortcut_edge.to_backward_bytes());
         }
     }
 
-    fn has_forward_batch_put(&mut self, key: &[u8]) {
-        match self {
-            WriteHandle::Single {
-                batch,
-                storage:
-                    Storage::Single(SingleDB { cf, .. }) | Storage::MultipleColumnFamilies { has_forward_cf: cf, .. },
-            } => batch.put_cf(cf, key, []),
-            WriteHandle::Multi {
-                has_forward_batch,
-                storage: Storage::MultipleDatabases { has_forward_db: SingleDB { cf, .. }, .. },
-                ..
-            } => has_forward_batch.put_cf(cf, key, []),
-            _ => unreachable!(),
+    fn merge_degree(&mut self, key: DegreeKey, delta: i64) {
+        let key = key.to_bytes();
+        if let Some(index) = &mut self.index {
+            // degree counters are only ever merged into, never put or deleted
+            if let Buffered::Merge(sum) = index.entry((KeySpace::Degree, key.to_vec())).or_insert(Buffered::Merge(0)) {
+                *sum += delta;
+            }
         }
+        let (batch, cf) = self.batch(KeySpace::Degree, &key);
+        batch.merge_cf(cf, key, delta.to_le_bytes());
     }
 
-    fn has_backward_batch_put(&mut self, key: &[u8]) {
-        match self {
-            WriteHandle::Single {
-                batch,
-                storage:
-                    Storage::Single(SingleDB { cf, .. }) | Storage::MultipleColumnFamilies { has_backward_cf: cf, .. },
-            } => batch.put_cf(cf, key, []),
-            WriteHandle::Multi {
-                has_backward_batch,
-                storage: Storage::MultipleDatabases { has_backward_db: SingleDB { cf, .. }, .. },
-                ..
-            } => has_backward_batch.put_cf(cf, key, []),
-            _ => unreachable!(),
+    fn put(&mut self, key_space: KeySpace, key: &[u8]) {
+        let (batch, cf) = self.batch(key_space, key);
+        batch.put_cf(cf, key, []);
+        let (keys, bytes) = &mut self.written[key_space as usize];
+        *keys += 1;
+        *bytes += key.len() as u64;
+        if let Some(recorded) = &mut self.recorded {
+            recorded.push((key_space, key.to_vec()));
+        }
+        if let Some(index) = &mut self.index {
+            index.insert((key_space, key.to_vec()), Buffered::Put);
         }
     }
 
-    fn relates_forward_batch_put(&mut self, key: &[u8]) {
-        match self {
-            WriteHandle::Single {
-                batch,
-                storage:
-                    Storage::Single(SingleDB { cf, .. }) | Storage::MultipleColumnFamilies { relates_forward_cf: cf, .. },
-            } => batch.put_cf(cf, key, []),
-            WriteHandle::Multi {
-                relates_forward_batch,
-                storage: Storage::MultipleDatabases { relates_forward_db: SingleDB { cf, .. }, .. },
-                ..
-            } => relates_forward_batch.put_cf(cf, key, []),
-            _ => unreachable!(),
+    fn delete(&mut self, key_space: KeySpace, key: &[u8]) {
+        let (batch, cf) = self.batch(key_space, key);
+        batch.delete_cf(cf, key);
+        if let Some(index) = &mut self.index {
+            index.insert((key_space, key.to_vec()), Buffered::Delete);
         }
     }
 
-    fn relates_backward_batch_put(&mut self, key: &[u8]) {
-        match self {
-            WriteHandle::Single {
-                batch,
-                storage:
-                    Storage::Single(SingleDB { cf, .. }) | Storage::MultipleColumnFamilies { relates_backward_cf: cf, .. },
-            } => batch.put_cf(cf, key, []),
-            WriteHandle::Multi {
-                relates_backward_batch,
-                storage: Storage::MultipleDatabases { relates_backward_db: SingleDB { cf, .. }, .. },
-                ..
-            } => relates_backward_batch.put_cf(cf, key, []),
-            _ => unreachable!(),
-        }
+    fn batch(&mut self, key_space: KeySpace, key: &[u8]) -> (&mut WriteBatch, &ColumnFamily) {
+        let (db_index, _, cf) = self.storage.locate(key_space, key);
+        (&mut self.batches[db_index], cf)
     }
+}
 
-    fn relation_sibling_batch_put(&mut self, key: &[u8]) {
-        match self {
-            WriteHandle::Single {
-                batch,
-                storage:
-                    Storage::Single(SingleDB { cf, .. }) | Storage::MultipleColumnFamilies { relation_sibling_cf: cf, .. },
-            } => batch.put_cf(cf, key, []),
-            WriteHandle::Multi {
-                relation_sibling_batch,
-                storage: Storage::MultipleDatabases { relation_sibling_db: SingleDB { cf, .. }, .. },
-                ..
-            } => relation_sibling_batch.put_cf(cf, key, []),
-            _ => unreachable!(),
+impl<'a> Reads for WriteHandle<'a> {
+    fn siblings(&self) -> Siblings {
+        self.storage.siblings
+    }
+
+    /// Merges the keys buffered in the index into the storage's, in key order. Buffered deletes hide stored keys. Only
+    /// keys buffered for the column family that holds `location` are merged, as only those are in the stored scan.
+    fn scan<'s>(
+        &'s self,
+        snapshot: &'s ReadSnapshot<'_>,
+        key_space: KeySpace,
+        location: &[u8],
+        prefix: &[u8],
+        start: &[u8],
+    ) -> impl Iterator<Item = Result<Box<[u8]>, StorageError>> + use<'s, 'a> {
+        let stored = self.storage.scan(snapshot, key_space, location, prefix, start);
+        let prefix = prefix.to_vec();
+        let storage = self.storage;
+        let (db_index, _, cf) = storage.locate(key_space, location);
+        let buffered = self
+            .index
+            .as_ref()
+            .map(|index| index.range((key_space, start.to_vec())..))
+            .into_iter()
+            .flatten()
+            .take_while(move |((buffered_key_space, key), _)| {
+                *buffered_key_space == key_space && key.starts_with(&prefix)
+            })
+            .filter(move |((_, key), _)| {
+                // in sharded and per-type layouts, keys with the same prefix can still be held elsewhere
+                let (key_db_index, _, key_cf) = storage.locate(key_space, key);
+                key_db_index == db_index && ptr::eq(key_cf, cf)
+            });
+        stored
+            .merge_join_by(buffered, |stored, ((_, key), _)| match stored {
+                Ok(stored) => (**stored).cmp(key.as_slice()),
+                // pass errors on as soon as they come up
+                Err(_) => cmp::Ordering::Less,
+            })
+            .filter_map(|entry| match entry {
+                EitherOrBoth::Left(stored) => Some(stored),
+                EitherOrBoth::Right(((_, key), buffered)) | EitherOrBoth::Both(_, ((_, key), buffered)) => {
+                    (*buffered != Buffered::Delete).then(|| Ok(key.as_slice().into()))
+                }
+            })
+    }
+
+    /// Reads buffered keys from the index, and degree counters with their buffered deltas added.
+    fn get(
+        &self,
+        snapshot: &ReadSnapshot<'_>,
+        key_space: KeySpace,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        match self.index.as_ref().and_then(|index| index.get(&(key_space, key.to_vec()))) {
+            None => self.storage.get(snapshot, key_space, key),
+            Some(Buffered::Put) => Ok(Some(Vec::new())),
+            Some(Buffered::Delete) => Ok(None),
+            Some(&Buffered::Merge(delta)) => {
+                let degree = match self.storage.get(snapshot, key_space, key)? {
+                    Some(value) => {
+                        decode_degree(&value).ok_or_else(|| StorageError::Decode { key_space, bytes: value.into() })?
+                    }
+                    None => 0,
+                };
+                Ok(Some((degree + delta).to_le_bytes().to_vec()))
+            }
         }
     }
 }
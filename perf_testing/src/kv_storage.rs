@@ -1,7 +1,13 @@
 use std::{path::Path, sync::Arc, time::Instant};
 
 use speedb::{
-    Direction::Forward, Error, IteratorMode, IteratorMode::From, Options, SstFileWriter, WriteBatch, WriteOptions, DB, ColumnFamily, BoundColumnFamily,
+    BoundColumnFamily, ColumnFamily, Direction::Forward, Error, IteratorMode, IteratorMode::From, Options,
+    SstFileWriter, WriteBatch, WriteOptions, DB,
+};
+
+use bench_utils::{
+    faults::{Faults, Point},
+    progress::Stall,
 };
 
 use crate::{
@@ -10,26 +16,38 @@ use crate::{
     memtable::Memtable,
 };
 
+const CFS: [&str; 4] = ["cf1", "cf2", "cf3", "cf0"];
+
 pub(crate) struct Storage<'a> {
     pub db: Arc<DB>,
     sst_writer: SstFileWriter<'a>,
     sst_counter: usize,
     write_options: WriteOptions,
+    /// Failures to inject into writes, ingestion, and the reads of its readers, shared with the other databases.
+    faults: Arc<Faults>,
 }
 
 impl<'a> Storage<'a> {
     const EMPTY_VALUE: [u8; 0] = [];
 
     pub(crate) fn new(path: &Path, options: &'a Options) -> Storage<'a> {
-        let db = Arc::new(DB::open_cf(options, path, ["cf1", "cf2", "cf3", "cf0"]).unwrap());
+        let db = Arc::new(DB::open_cf(options, path, CFS).unwrap());
         let sst_writer = SstFileWriter::create(options);
         let mut write_options = WriteOptions::default();
         write_options.disable_wal(true);
-        Storage { db, sst_writer, sst_counter: 0, write_options }
+        Storage { db, sst_writer, sst_counter: 0, write_options, faults: Arc::default() }
+    }
+
+    pub(crate) fn with_faults(self, faults: Arc<Faults>) -> Storage<'a> {
+        Storage { faults, ..self }
+    }
+
+    pub(crate) fn faults(&self) -> &Faults {
+        &self.faults
     }
 
     pub(crate) fn new_reader(&mut self) -> StorageReader {
-        StorageReader { db: self.db.clone() }
+        StorageReader { db: self.db.clone(), faults: self.faults.clone() }
     }
 
     pub(crate) fn write_to_sst_and_ingest(&mut self, memtable: Memtable) -> Result<(Measurement, Measurement), Error> {
@@ -48,6 +66,7 @@ impl<'a> Storage<'a> {
         let sst_write_measurement = Measurement::new(key_count, KEY_SIZE, file_size, start_time.elapsed());
 
         let start_time = Instant::now();
+        self.faults.inject(Point::Ingest);
         self.db.ingest_external_file(vec![path])?;
         let ingest_measurement = Measurement::new(key_count, KEY_SIZE, file_size, start_time.elapsed());
 
@@ -58,23 +77,66 @@ impl<'a> Storage<'a> {
         self.db.iterator(IteratorMode::Start).count()
     }
 
+    /// How many bytes compaction estimates it still has to rewrite, over all column families.
+    pub(crate) fn pending_compaction_bytes(&self) -> u64 {
+        CFS.iter()
+            .filter_map(|&cf| self.db.cf_handle(cf))
+            .map(|cf| self.db.property_int_value_cf(&cf, "rocksdb.estimate-pending-compaction-bytes").unwrap())
+            .map(Option::unwrap_or_default)
+            .sum()
+    }
+
+    /// Whether the database is slowing down or blocking writes.
+    pub(crate) fn write_stall(&self) -> Stall {
+        let property = |name| self.db.property_int_value(name).unwrap().unwrap_or_default();
+        match (property("rocksdb.is-write-stopped"), property("rocksdb.actual-delayed-write-rate")) {
+            (0, 0) => Stall::None,
+            (0, _) => Stall::Delayed,
+            _ => Stall::Stopped,
+        }
+    }
+
+    /// Memory held by the databases: memtables, the block cache, and the index and filter blocks of open SSTs.
+    pub(crate) fn memory_usage<'s>(storages: impl Iterator<Item = &'s Self> + Clone) -> Vec<(&'static str, u64)>
+    where
+        'a: 's,
+    {
+        let values = |property| {
+            storages.clone().flat_map(move |storage| {
+                CFS.iter()
+                    .filter_map(|&cf| storage.db.cf_handle(cf))
+                    .map(move |cf| storage.db.property_int_value_cf(&cf, property).unwrap().unwrap_or_default())
+            })
+        };
+        vec![
+            ("memtables", values("rocksdb.cur-size-all-mem-tables").sum()),
+            // the databases are opened from clones of the same options, which share one block cache
+            ("block cache", values("rocksdb.block-cache-usage").max().unwrap_or_default()),
+            ("table readers", values("rocksdb.estimate-table-readers-mem").sum()),
+        ]
+    }
+
     pub(crate) fn put(&self, keys: &[Key], cf: &Arc<BoundColumnFamily>) {
         let mut write_batch = WriteBatch::default();
         keys.iter().for_each(|key| write_batch.put_cf(cf, key.key, Storage::EMPTY_VALUE));
+        self.faults.inject(Point::Write);
         self.db.write_opt(write_batch, &self.write_options).unwrap();
     }
 }
 
 pub(crate) struct StorageReader {
     db: Arc<DB>,
+    faults: Arc<Faults>,
 }
 
 impl StorageReader {
     pub(crate) fn get(&self, key: Key) -> Option<Vec<u8>> {
+        self.faults.inject(Point::Read);
         self.db.get(key.key).unwrap()
     }
 
     pub(crate) fn iterate_10(&self, prefix: [u8; 16]) -> usize {
+        self.faults.inject(Point::Read);
         self.db.iterator(From(&prefix, Forward)).take(10).count()
     }
 }
@@ -6,16 +6,28 @@ mod measurement;
 mod memtable;
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     path::Path,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
         Arc, RwLock,
     },
     thread,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
+use bench_utils::{
+    affinity::{Placement, Plan},
+    disk::{self, DiskUsage},
+    faults::{self, Faults},
+    interrupt, memory,
+    progress::{self, ActiveThreads, LatencyHistogram, Rate},
+    sweep::Sweep,
+    trials::{Trial, Trials},
+    units::Bytes,
+    warmup::Warmup,
+};
+use clap::{arg, command, value_parser, ArgAction};
 use itertools::Itertools;
 use rand::{thread_rng, Rng};
 use speedb::{
@@ -35,6 +47,7 @@ const READER_LOG_PERIOD: usize = 10_000;
 fn read_random_full_keys(reader: StorageReader, stop: Arc<AtomicBool>, read_queue: Arc<RwLock<Vec<Key>>>) {
     let mut rng = thread_rng();
     let mut matches = 0;
+    let mut failed = 0;
     let start = Instant::now();
 
     let mut current = start;
@@ -43,8 +56,9 @@ fn read_random_full_keys(reader: StorageReader, stop: Arc<AtomicBool>, read_queu
             let duration = start.elapsed();
             let rate = (attempts as f64) / duration.as_secs_f64();
             println!(
-                "Total of {} get queries, which matched {} times, in {:.2?}. Average get rate: {:.2} reads/sec",
-                attempts, matches, duration, rate
+                "Total of {} get queries, which matched {} times and failed {} times, in {:.2?}. Average get rate: \
+                 {:.2} reads/sec",
+                attempts, matches, failed, duration, rate
             );
             break;
         }
@@ -56,7 +70,10 @@ fn read_random_full_keys(reader: StorageReader, stop: Arc<AtomicBool>, read_queu
                 break vec.remove(rng.gen_range(0..len));
             }
         };
-        matches += reader.get(key).is_some() as usize;
+        match faults::catch(|| reader.get(key)) {
+            Ok(value) => matches += value.is_some() as usize,
+            Err(_) => failed += 1,
+        }
 
         if attempts % READER_LOG_PERIOD == 0 {
             let now = Instant::now();
@@ -72,6 +89,7 @@ fn read_prefix_iter(reader: StorageReader, stop: Arc<AtomicBool>) {
     let mut rng = thread_rng();
     let mut matches = 0;
     let mut iterated = 0;
+    let mut failed = 0;
     let start = Instant::now();
 
     let mut current = start;
@@ -80,14 +98,19 @@ fn read_prefix_iter(reader: StorageReader, stop: Arc<AtomicBool>) {
             let duration = start.elapsed();
             let rate = (attempts as f64) / duration.as_secs_f64();
             print!("Did {attempts} prefix queries, which matched >=1 element {matches} times ");
+            print!("and failed {failed} times, ");
             println!("for a total of {iterated}, in {duration:.2?}. Rate of prefix seeks: {rate:.2} reads/sec");
             break;
         }
 
         let prefix: [u8; 16] = rng.gen();
-        let read = reader.iterate_10(prefix);
-        iterated += read as usize;
-        matches += (read == 0) as usize;
+        match faults::catch(|| reader.iterate_10(prefix)) {
+            Ok(read) => {
+                iterated += read;
+                matches += (read == 0) as usize;
+            }
+            Err(_) => failed += 1,
+        }
 
         if attempts % READER_LOG_PERIOD == 0 {
             let now = Instant::now();
@@ -101,9 +124,96 @@ fn read_prefix_iter(reader: StorageReader, stop: Arc<AtomicBool>) {
 
 const SST_SIZE_TARGET: usize = 64_000_000;
 const SST_COUNT: usize = 320;
+/// The column families that writer threads spread over, each in its own database.
+const CFS: [&str; 4] = ["cf0", "cf1", "cf2", "cf3"];
+const SWEEP_AXES: [&str; 4] = ["threads", "cfs", "batch-size", "keys"];
+
+/// One combination of a sweep over `test_direct`.
+#[derive(Copy, Clone, Debug)]
+struct DirectConfig {
+    threads: usize,
+    /// How many of `CFS` the threads write to, round-robin.
+    cfs: usize,
+    /// Keys per write batch.
+    batch_size: usize,
+    /// Keys written in total, across all threads.
+    keys: usize,
+}
+
+impl DirectConfig {
+    fn from_params(params: &BTreeMap<String, String>) -> Result<Self, String> {
+        let get = |name: &str, default: usize| match params.get(name) {
+            Some(value) => value.parse().map_err(|_| format!("Unexpected {name}: '{value}'. Expected a number.")),
+            None => Ok(default),
+        };
+        let config = Self {
+            threads: get("threads", 1)?,
+            cfs: get("cfs", 1)?,
+            batch_size: get("batch-size", 128)?,
+            keys: get("keys", SST_SIZE_TARGET * SST_COUNT / KEY_SIZE)?,
+        };
+        if config.threads == 0 || config.batch_size == 0 || !(1..=CFS.len()).contains(&config.cfs) {
+            return Err(format!("Unexpected sweep combination: {config:?}"));
+        }
+        Ok(config)
+    }
+}
 
 fn main() {
+    let args = command!()
+        .arg(
+            arg!(--sweep <SPEC> "axes to sweep over: threads, cfs, batch-size, keys (e.g. 'threads=1,4 cfs=1,4'); \
+                 without one, a single writer thread on one column family")
+            .value_parser(value_parser!(Sweep)),
+        )
+        .arg(
+            arg!(--trials <TRIALS> "how many times to run each combination")
+                .value_parser(value_parser!(usize))
+                .default_value("1"),
+        )
+        .arg(
+            arg!(--shuffle "interleave the trials of all combinations, so that drift over the run doesn't favour the \
+                 earlier ones")
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(-w --warmup <SECONDS> "discard results for this many seconds, or until throughput settles with 'auto' \
+                 (writes before then mostly land in memtables)")
+            .value_parser(value_parser!(Warmup))
+            .default_value("auto"),
+        )
+        .arg(
+            arg!(--pin <PLACEMENT> "os / round-robin / compact / CPU list (e.g. 0,2,4-7) to pin the writers to")
+                .value_parser(value_parser!(Placement))
+                .default_value("os"),
+        )
+        .arg(
+            arg!(--faults <SPEC> "fail storage calls, e.g. 'write=0.01,ingest=after:10' (points: write, read, ingest)")
+                .value_parser(value_parser!(Faults)),
+        )
+        .arg(
+            arg!(--memtables "after the trials, also fill memtables, write them out as SSTs, and ingest those")
+                .action(ArgAction::SetTrue),
+        )
+        .get_matches();
+    let sweep = args.get_one::<Sweep>("sweep").cloned().unwrap_or_else(|| Sweep { axes: Vec::new() });
+    sweep.check_axes(&SWEEP_AXES).unwrap_or_else(|err| panic!("{err}"));
+    let combinations = sweep.combinations();
+    let configs = combinations
+        .iter()
+        .map(|params| DirectConfig::from_params(params).unwrap_or_else(|err| panic!("{err}")))
+        .collect_vec();
+    let pin = args.get_raw("pin").unwrap().map(|value| value.to_string_lossy()).join(",");
+    let placement = args.get_one::<Placement>("pin").unwrap().plan().expect("could not plan thread placement");
+    let faults = args.get_one::<Faults>("faults").cloned().unwrap_or_default();
+    let warmup = *args.get_one::<Warmup>("warmup").unwrap();
+    let warmup_spec = args.get_raw("warmup").unwrap().map(|value| value.to_string_lossy()).join(",");
+    if faults.is_enabled() {
+        faults::set_panic_hook(false);
+    }
+
     let storage_dir = Path::new("testing-store");
+    interrupt::install_handler();
 
     let options = {
         let mut options = Options::default();
@@ -131,62 +241,178 @@ fn main() {
     // move || read_prefix_iter(reader, stop)
     // });
 
-    test_direct(storage_dir, &options, 1, ["cf0", "cf0", "cf0", "cf0"]);
-    test_direct(storage_dir, &options, 4, ["cf0", "cf0", "cf0", "cf0"]);
-    test_direct(storage_dir, &options, 4, ["cf0", "cf1", "cf2", "cf3"]);
-    // test_memtables(storage_dir, &options);
-
+    let mut trials =
+        Trials::new(env!("CARGO_PKG_NAME"), *args.get_one("trials").unwrap()).shuffled(args.get_flag("shuffle"));
+    for (params, &config) in combinations.iter().zip(&configs) {
+        let name = if params.is_empty() {
+            "direct".to_owned()
+        } else {
+            params.iter().map(|(axis, value)| format!("{axis}={value}")).join(" ")
+        };
+        let params = SWEEP_AXES.into_iter().filter_map(|axis| Some((axis, params.get(axis)?.clone())));
+        let params = params.chain([("pin", pin.clone()), ("warmup", warmup_spec.clone())]);
+        let fault_spec = args.get_raw("faults").map(|spec| spec.map(|value| value.to_string_lossy()).join(","));
+        let params = params.chain(fault_spec.map(|spec| ("faults", spec)));
+        trials.case_with_params(name, params, "MB/sec", {
+            let options = &options;
+            let placement = &placement;
+            let faults = &faults;
+            move || test_direct(storage_dir, options, placement, faults, warmup, config)
+        });
+    }
+    let summaries = trials.run().into_iter().map(|(_, summary)| summary);
+    let results = combinations.into_iter().zip(summaries).filter_map(|(params, summary)| Some((params, summary?)));
+    sweep.print_table("MB/sec", &results.collect_vec());
+    if args.get_flag("memtables") && !interrupt::interrupted() {
+        test_memtables(storage_dir, &options, &faults);
+    }
+    interrupt::remove_interrupted_store(storage_dir);
     // print!("{}", options.get_statistics().unwrap());
     // stop.store(true, Ordering::Relaxed);
     // key_reader_thread.join().unwrap();
     // prefix_reader_thread.join().unwrap();
 }
 
-fn test_direct(storage_dir: &Path, options: &Options, num_threads: usize, cfs: [&str; 4]) {
+/// Writes into a fresh store and returns the steady-state write throughput in MB/sec, with the memory used. If the
+/// writers finish before the warm-up does, there is no steady state, and the throughput of the whole run is returned.
+fn test_direct(
+    storage_dir: &Path,
+    options: &Options,
+    placement: &Plan,
+    faults: &Faults,
+    warmup: Warmup,
+    config: DirectConfig,
+) -> Trial {
+    let DirectConfig { threads: num_threads, cfs, batch_size, keys } = config;
+    println!("{}", placement.describe(&[("writers", 0..num_threads)]));
     if storage_dir.exists() {
         std::fs::remove_dir_all(storage_dir).expect("could not remove data dir");
     }
 
-    let dbs = cfs
+    // every trial starts counting afresh, with one count over all the databases
+    let faults = Arc::new(faults.clone());
+    let dbs = CFS[..cfs]
         .iter()
-        .unique()
-        .map(|&cf| (cf, Storage::new(&storage_dir.join(format!("db{cf}")), options)))
+        .map(|&cf| (cf, Storage::new(&storage_dir.join(format!("db{cf}")), options).with_faults(faults.clone())))
         .collect::<HashMap<_, _>>();
 
+    let written = AtomicUsize::new(0);
+    let failed_writes = AtomicUsize::new(0);
+    let write_latencies = LatencyHistogram::default();
+    let active = ActiveThreads::default();
+    let mut written_keys = Rate::new(&written);
+    let status = |interval: Duration| {
+        let writes = write_latencies.take();
+        format!(
+            "{:.0} writes/sec, {:.0} keys/sec, {} threads, write latency {writes}, pending compaction {}, stall: {}",
+            writes.count() as f64 / interval.as_secs_f64(),
+            written_keys.per_sec(interval),
+            active.count(),
+            Bytes(dbs.values().map(Storage::pending_compaction_bytes).sum()),
+            dbs.values().map(Storage::write_stall).max().unwrap_or_default(),
+        )
+    };
     let start = Instant::now();
-    thread::scope(|s| {
-        let dbs = &dbs;
-        dbg!(num_threads);
-        for i in 0..num_threads {
-            s.spawn(move || {
-                let cf = cfs[i];
-                let storage = &dbs[cf];
-                write_direct_to_storage(
-                    storage,
-                    storage.db.cf_handle(dbg!(cf)).unwrap(),
-                    SST_SIZE_TARGET * SST_COUNT / KEY_SIZE / num_threads,
-                    SST_SIZE_TARGET / KEY_SIZE / num_threads,
-                )
-            });
-        }
-    });
-    println!("Total time: {:.2?}", start.elapsed());
+    let (warmup_end, profile) = memory::track(
+        || Storage::memory_usage(dbs.values()),
+        || {
+            progress::track(status, || {
+                thread::scope(|s| {
+                    let dbs = &dbs;
+                    let written = &written;
+                    let failed_writes = &failed_writes;
+                    let write_latencies = &write_latencies;
+                    let active = &active;
+                    let writers = (0..num_threads)
+                        .map(|i| {
+                            s.spawn(move || {
+                                placement.pin(i);
+                                let _active = active.enter();
+                                let cf = CFS[i % cfs];
+                                let storage = &dbs[cf];
+                                write_direct_to_storage(
+                                    storage,
+                                    storage.db.cf_handle(cf).unwrap(),
+                                    keys / num_threads,
+                                    (SST_SIZE_TARGET / KEY_SIZE / num_threads).clamp(1, (keys / num_threads).max(1)),
+                                    batch_size,
+                                    (written, failed_writes),
+                                    write_latencies,
+                                )
+                            })
+                        })
+                        .collect_vec();
+                    warmup
+                        .wait(written, || interrupt::interrupted() || writers.iter().all(|writer| writer.is_finished()))
+                })
+            })
+        },
+    );
+    println!("Warm-up: {:.2?}, discarding {} keys", warmup_end.elapsed, warmup_end.progress);
+    if !warmup_end.steady {
+        println!("Throughput did not settle during the warm-up");
+    }
+    let written_total = written.load(Ordering::Relaxed);
+    let steady_keys = written_total - warmup_end.progress;
+    let steady_state = if steady_keys > 0 {
+        let steady_state =
+            Measurement::new(steady_keys, KEY_SIZE, (steady_keys * KEY_SIZE) as u64, warmup_end.at.elapsed());
+        println!("Steady state: {steady_state}");
+        steady_state
+    } else {
+        // an empty window would be recorded as 0 MB/sec, as if it had been measured
+        let whole_run = Measurement::new(written_total, KEY_SIZE, (written_total * KEY_SIZE) as u64, start.elapsed());
+        println!("The writers finished during the warm-up, so the whole run is measured instead: {whole_run}");
+        whole_run
+    };
+    if faults.is_enabled() {
+        println!("Injected faults: {}", faults.summary());
+        println!("Failed writes: {}", failed_writes.load(Ordering::Relaxed));
+    }
+    let pending_compaction_bytes = dbs.values().map(Storage::pending_compaction_bytes).sum::<u64>();
+    println!("Pending compaction bytes: {pending_compaction_bytes}");
 
     let start = Instant::now();
     let count: usize = dbs.values().map(Storage::total_keys).sum();
     println!("Total keys in db: {}, in time: {:.2?}", count, start.elapsed());
+
+    let disk_usage = CFS[..cfs]
+        .iter()
+        .map(|cf| {
+            let usage = DiskUsage::of(&storage_dir.join(format!("db{cf}"))).expect("could not read data dir");
+            println!("Disk usage of db{cf}: {usage}");
+            usage
+        })
+        .sum::<DiskUsage>();
+    // values are empty, so the keys are all there is
+    let logical_bytes = (count * KEY_SIZE) as u64;
+    println!("Disk usage: {disk_usage}");
+    if let Some(amplification) = disk::space_amplification(disk_usage.total(), logical_bytes) {
+        println!(
+            "Logical bytes: {}, space amplification: {amplification:.2} ({:.2} in SSTs)",
+            Bytes(logical_bytes),
+            disk::space_amplification(disk_usage.sst, logical_bytes).unwrap(),
+        );
+    }
+
+    memory::print_peaks([&profile]);
+
+    Trial::from((steady_state.throughput() / 1_000_000.0, profile))
 }
 
-fn test_memtables(storage_dir: &Path, options: &Options) {
+fn test_memtables(storage_dir: &Path, options: &Options, faults: &Faults) {
     if storage_dir.exists() {
         std::fs::remove_dir_all(storage_dir).expect("could not remove data dir");
     }
 
-    let mut storage = Storage::new(storage_dir, options);
+    let mut storage = Storage::new(storage_dir, options).with_faults(Arc::new(faults.clone()));
 
     let start = Instant::now();
     write_memtables_to_storage(&mut storage, SST_SIZE_TARGET, SST_COUNT);
     println!("Total time: {:.2?}", start.elapsed());
+    if faults.is_enabled() {
+        println!("Injected faults: {}", storage.faults().summary());
+    }
 
     let start = Instant::now();
     let count = storage.total_keys();
@@ -200,9 +426,15 @@ fn write_memtables_to_storage(storage: &mut Storage, sst_size_target: usize, sst
         let mut memtable = Memtable::new(sst_size_target);
         let (fill_measurement, _read_queue_add) = fill_memtable(&mut memtable);
         println!("Memtable fill: {}", fill_measurement);
-        let (sst_measurement, ingest_measurement) = storage.write_to_sst_and_ingest(memtable).unwrap();
-        println!("SST write: {}", sst_measurement);
-        println!("SST ingest: {}", ingest_measurement);
+        match faults::catch(|| storage.write_to_sst_and_ingest(memtable)) {
+            Ok(measurements) => {
+                let (sst_measurement, ingest_measurement) = measurements.unwrap();
+                println!("SST write: {}", sst_measurement);
+                println!("SST ingest: {}", ingest_measurement);
+            }
+            // the keys of a failed ingest are lost, like those of a failed write
+            Err(fault) => println!("SST ingest failed: {fault}"),
+        }
     }
 }
 
@@ -224,7 +456,15 @@ fn fill_memtable(memtable: &mut Memtable) -> (Measurement, Vec<Key>) {
 }
 
 #[allow(dead_code)]
-fn write_direct_to_storage(storage: &Storage, cf: Arc<BoundColumnFamily>, key_count: usize, batch_size: usize) {
+fn write_direct_to_storage(
+    storage: &Storage,
+    cf: Arc<BoundColumnFamily>,
+    key_count: usize,
+    batch_size: usize,
+    write_batch_size: usize,
+    (written, failed): (&AtomicUsize, &AtomicUsize),
+    write_latencies: &LatencyHistogram,
+) {
     for (iteration, _) in (0..key_count).step_by(batch_size).enumerate() {
         // println!("---Iteration {iteration} ---");
         let mut rng = thread_rng();
@@ -234,8 +474,18 @@ fn write_direct_to_storage(storage: &Storage, cf: Arc<BoundColumnFamily>, key_co
             keys.0
         };
         let start = Instant::now();
-        for keys in generated.chunks(128) {
-            storage.put(keys, &cf);
+        for keys in generated.chunks(write_batch_size) {
+            if interrupt::interrupted() {
+                return;
+            }
+            let write_start = Instant::now();
+            // a write failed by an injected fault is counted and skipped, like a write whose error is tolerated
+            if faults::catch(|| storage.put(keys, &cf)).is_err() {
+                failed.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            write_latencies.record(write_start.elapsed());
+            written.fetch_add(keys.len(), Ordering::Relaxed);
         }
         let storage_write_measurement =
             Measurement::new(batch_size, KEY_SIZE, (batch_size * KEY_SIZE) as u64, start.elapsed());
@@ -15,7 +15,7 @@ impl Measurement {
         Measurement { key_count, key_size, written_bytes, duration }
     }
 
-    fn throughput(&self) -> f64 {
+    pub(crate) fn throughput(&self) -> f64 {
         (self.written_bytes as f64) / self.duration.as_secs_f64()
     }
 }